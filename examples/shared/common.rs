@@ -29,12 +29,12 @@ pub fn setup_exit_handler() -> Arc<AtomicBool> {
 pub fn prepare_queue_file(queue_path: &str) -> File {
     println!("Cleaning queue file: {queue_path}");
     let _ = std::fs::remove_file(queue_path);
-    File::options()
-        .create_new(true)
-        .read(true)
-        .write(true)
-        .open(queue_path)
-        .unwrap()
+    let mut options = File::options();
+    options.create_new(true).read(true).write(true);
+    // Keep the queue private to this user, whatever the umask.
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    options.open(queue_path).unwrap()
 }
 
 pub fn cleanup_queue_file(queue_path: &str) {
@@ -52,8 +52,8 @@ use std::time::Duration;
 
 use crate::error::{Error, WaitError};
 use crate::futex::{Waiters, SPIN_ATTEMPTS};
-use crate::shmem::Region;
-use crate::{CacheAlignedAtomicSize, VERSION};
+use crate::shmem::{size_file, Region};
+use crate::{validate_element_layout, validate_region_size, CacheAlignedAtomicSize, VERSION};
 
 use consumer_state::{ConsumerRecoveryMode, ConsumerState};
 use producer_lane::ProducerLane;
@@ -193,11 +193,8 @@ impl QueueLayout {
 
     /// Reconstructs and validates the layout from an initialized header.
     fn from_header<T>(header: &SharedQueueHeader, region_size: usize) -> Result<Self, Error> {
+        validate_element_layout::<T>(header.payload_size, header.payload_align)?;
         let payload = Self::payload_from_header(header)?;
-        let expected = Layout::new::<T>();
-        if payload.size() != expected.size() || payload.align() != expected.align() {
-            return Err(Error::InvalidBufferSize);
-        }
         Self::from_header_with_payload(header, region_size, payload)
     }
 
@@ -286,6 +283,7 @@ impl SharedQueue {
         region: &Arc<Region>,
         layout_from_header: impl FnOnce(&SharedQueueHeader, usize) -> Result<QueueLayout, Error>,
     ) -> Result<Self, Error> {
+        validate_region_size(region.size(), size_of::<SharedQueueHeader>())?;
         let header = region.addr().cast::<SharedQueueHeader>();
         // SAFETY: regions are page-aligned (>= align_of::<SharedQueueHeader>()).
         let header_ref = unsafe { header.as_ref() };
@@ -445,7 +443,7 @@ impl SharedQueue {
     ///   initializer) and not resized while any handle is joined.
     unsafe fn create<T>(file: &File, config: &BroadcastConfig) -> Result<Self, Error> {
         let layout = QueueLayout::new::<T>(config)?;
-        file.set_len(layout.total as u64)?;
+        size_file(file, layout.total)?;
         let region = Region::map_file(file, layout.total)?;
         // SAFETY: caller guarantees this mapping is initialized exactly once.
         unsafe { Self::create_in_region::<T>(&region, config) }
@@ -457,6 +455,7 @@ impl SharedQueue {
     /// - `file` must refer to a live broadcast queue, not resized while joined.
     unsafe fn join<T>(file: &File) -> Result<Self, Error> {
         let file_size = file.metadata()?.len() as usize;
+        validate_region_size(file_size, size_of::<SharedQueueHeader>())?;
         let region = Region::map_file(file, file_size)?;
         // SAFETY: validated against the stored header.
         unsafe { Self::join_region::<T>(&region) }
@@ -469,6 +468,7 @@ impl SharedQueue {
     /// - `file` must refer to a live broadcast queue, not resized while joined.
     unsafe fn join_untyped(file: &File) -> Result<Self, Error> {
         let file_size = file.metadata()?.len() as usize;
+        validate_region_size(file_size, size_of::<SharedQueueHeader>())?;
         let region = Region::map_file(file, file_size)?;
         // SAFETY: validated against the stored header.
         unsafe { Self::join_region_untyped(&region) }
@@ -1649,6 +1649,14 @@ mod tests {
         assert_eq!(queue.header().payload_align, align_of::<Payload>());
     }
 
+    #[test]
+    fn join_rejects_region_smaller_than_header() {
+        let region = Region::alloc(NonZeroUsize::new(64).unwrap()).expect("alloc");
+        // SAFETY: validation rejects the region before reading the header.
+        let err = unsafe { SharedQueue::join_region::<u64>(&region) };
+        assert!(matches!(err, Err(Error::FileTooSmall { given: 64, .. })));
+    }
+
     #[test]
     fn typed_join_rejects_payload_layout_mismatch() {
         let config = BroadcastConfig {
@@ -1664,12 +1672,12 @@ mod tests {
         // Same payload size as `u64`, but different alignment.
         // SAFETY: the region is a live broadcast queue; validation should fail.
         let err = unsafe { SharedQueue::join_region::<[u8; 8]>(&region) };
-        assert!(matches!(err, Err(Error::InvalidBufferSize)));
+        assert!(matches!(err, Err(Error::ElementLayoutMismatch { .. })));
 
         // Different payload size.
         // SAFETY: the region is a live broadcast queue; validation should fail.
         let err = unsafe { SharedQueue::join_region::<[u8; 4]>(&region) };
-        assert!(matches!(err, Err(Error::InvalidBufferSize)));
+        assert!(matches!(err, Err(Error::ElementLayoutMismatch { .. })));
     }
 
     #[test]
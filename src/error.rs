@@ -8,6 +8,12 @@ pub enum Error {
         actual: u32,
     },
     InvalidBufferSize,
+    /// The file or region is smaller than the least a queue of its type can
+    /// occupy.
+    FileTooSmall {
+        given: usize,
+        minimum: usize,
+    },
     InvalidRegionAlignment {
         minimum: usize,
         actual: usize,
@@ -15,10 +21,37 @@ pub enum Error {
     Allocation(std::alloc::Layout),
     Io(std::io::Error),
     Mmap(std::io::Error),
+    Madvise(std::io::Error),
+    /// The backing file could not be sized, or its storage reserved.
+    Truncate(std::io::Error),
     ProducerSlotsExhausted,
     ConsumerSlotsExhausted,
     /// A recovery index was out of range for the queue's slot count.
     InvalidIndex,
+    /// The queue was created for an element type with a different layout.
+    ElementLayoutMismatch {
+        expected: std::alloc::Layout,
+        found: std::alloc::Layout,
+    },
+    /// Queue metadata exceeded the space reserved for it in the header.
+    MetadataTooLarge {
+        len: usize,
+        maximum: usize,
+    },
+    /// A single element does not fit in the bytes left after the header.
+    ItemTooLarge {
+        item_size: usize,
+        available: usize,
+    },
+    /// A byte queue message is longer than the queue can ever hold.
+    MessageTooLarge {
+        len: usize,
+        maximum: usize,
+    },
+    /// A checked byte queue message failed checksum verification.
+    ChecksumMismatch,
+    /// A byte queue frame's length prefix overruns the committed data.
+    CorruptFrame,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -26,7 +59,14 @@ pub enum WaitError {
     Timeout,
 }
 
-impl std::error::Error for Error {}
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(err) | Self::Mmap(err) | Self::Madvise(err) | Self::Truncate(err) => Some(err),
+            _ => None,
+        }
+    }
+}
 impl std::error::Error for WaitError {}
 
 impl Display for Error {
@@ -42,6 +82,9 @@ impl Display for Error {
                 actual & 0xFFFF,
             ),
             Self::InvalidBufferSize => write!(f, "invalid buffer size"),
+            Self::FileTooSmall { given, minimum } => {
+                write!(f, "file too small; given={given}; minimum={minimum}")
+            }
             Self::InvalidRegionAlignment { minimum, actual } => write!(
                 f,
                 "invalid region alignment; minimum={minimum}; actual={actual}"
@@ -54,9 +97,34 @@ impl Display for Error {
             ),
             Self::Io(err) => write!(f, "io; err={err}"),
             Self::Mmap(err) => write!(f, "mmap; err={err}"),
+            Self::Madvise(err) => write!(f, "madvise; err={err}"),
+            Self::Truncate(err) => write!(f, "truncate; err={err}"),
             Self::ProducerSlotsExhausted => write!(f, "producer slots exhausted"),
             Self::ConsumerSlotsExhausted => write!(f, "consumer slots exhausted"),
             Self::InvalidIndex => write!(f, "invalid index"),
+            Self::ElementLayoutMismatch { expected, found } => write!(
+                f,
+                "element layout mismatch; expected={}:{}; found={}:{}",
+                expected.size(),
+                expected.align(),
+                found.size(),
+                found.align(),
+            ),
+            Self::MetadataTooLarge { len, maximum } => {
+                write!(f, "metadata too large; len={len}; maximum={maximum}")
+            }
+            Self::ItemTooLarge {
+                item_size,
+                available,
+            } => write!(
+                f,
+                "item too large; item_size={item_size}; available={available}"
+            ),
+            Self::MessageTooLarge { len, maximum } => {
+                write!(f, "message too large; len={len}; maximum={maximum}")
+            }
+            Self::ChecksumMismatch => write!(f, "checksum mismatch"),
+            Self::CorruptFrame => write!(f, "corrupt frame"),
         }
     }
 }
@@ -87,6 +155,79 @@ mod tests {
         assert_eq!(err.to_string(), "invalid version; expected=1.0; found=3.7");
     }
 
+    #[test]
+    fn test_element_layout_mismatch_display() {
+        let err = Error::ElementLayoutMismatch {
+            expected: std::alloc::Layout::new::<u64>(),
+            found: std::alloc::Layout::new::<u32>(),
+        };
+        assert_eq!(
+            err.to_string(),
+            "element layout mismatch; expected=8:8; found=4:4"
+        );
+    }
+
+    #[test]
+    fn test_item_too_large_display() {
+        let err = Error::ItemTooLarge {
+            item_size: 4096,
+            available: 128,
+        };
+        assert_eq!(
+            err.to_string(),
+            "item too large; item_size=4096; available=128"
+        );
+    }
+
+    #[test]
+    fn test_message_too_large_display() {
+        let err = Error::MessageTooLarge {
+            len: 100,
+            maximum: 56,
+        };
+        assert_eq!(err.to_string(), "message too large; len=100; maximum=56");
+    }
+
+    #[test]
+    fn test_checksum_mismatch_display() {
+        assert_eq!(Error::ChecksumMismatch.to_string(), "checksum mismatch");
+    }
+
+    #[test]
+    fn test_corrupt_frame_display() {
+        assert_eq!(Error::CorruptFrame.to_string(), "corrupt frame");
+    }
+
+    #[test]
+    fn test_file_too_small_display() {
+        let err = Error::FileTooSmall {
+            given: 16,
+            minimum: 256,
+        };
+        assert_eq!(err.to_string(), "file too small; given=16; minimum=256");
+    }
+
+    #[test]
+    fn test_truncate_display() {
+        let err = Error::Truncate(std::io::Error::other("no space"));
+        assert_eq!(err.to_string(), "truncate; err=no space");
+    }
+
+    #[test]
+    fn test_source_chains_io_error() {
+        use std::error::Error as _;
+
+        let err = Error::Mmap(std::io::Error::from(std::io::ErrorKind::OutOfMemory));
+        let source = err.source().expect("mmap error has a source");
+        assert_eq!(
+            source
+                .downcast_ref::<std::io::Error>()
+                .map(|err| err.kind()),
+            Some(std::io::ErrorKind::OutOfMemory)
+        );
+        assert!(Error::InvalidMagic.source().is_none());
+    }
+
     #[test]
     fn test_wait_timeout_display() {
         assert_eq!(WaitError::Timeout.to_string(), "wait timed out");
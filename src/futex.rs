@@ -76,6 +76,8 @@ impl Waiters {
     /// whose `check` is itself expensive (e.g. it scans many lanes) should pass
     /// a smaller count so the total spin work stays bounded; see
     /// [`SPIN_ATTEMPTS`] for the baseline used by a unit-cost check.
+    ///
+    /// A zero `timeout` runs `check` exactly once, without spinning or sleeping.
     pub(crate) fn wait_for<T>(
         &self,
         cursor: &AtomicUsize,
@@ -86,6 +88,9 @@ impl Waiters {
         if let Some(value) = check() {
             return Ok(value);
         }
+        if timeout.is_zero() {
+            return Err(WaitError::Timeout);
+        }
 
         // Taken only after the first check so an immediately satisfied call
         // never reads the clock; the timeout still bounds the spin below.
@@ -1,18 +1,25 @@
 #![deny(unsafe_op_in_unsafe_fn)]
 
-use core::sync::atomic::AtomicUsize;
+use core::{alloc::Layout, sync::atomic::AtomicUsize};
+use error::Error;
 
 // NB: To simplify casting we only support 64bit or wider systems.
 const _: () = assert!(size_of::<usize>() >= size_of::<u64>());
 
 pub mod broadcast;
+#[cfg(feature = "checksum")]
+mod checksum;
 pub mod error;
 mod futex;
 pub mod mpmc;
 mod shmem;
 pub mod spsc;
 
-pub(crate) const VERSION_MAJOR: u16 = 2;
+#[cfg(target_os = "linux")]
+pub use shmem::memfd_create;
+pub use shmem::Advice;
+
+pub(crate) const VERSION_MAJOR: u16 = 3;
 pub(crate) const VERSION_PATCH: u16 = 0;
 pub(crate) const VERSION: u32 = (VERSION_MAJOR as u32) << 16 | VERSION_PATCH as u32;
 
@@ -24,9 +31,40 @@ pub(crate) const fn normalized_capacity(capacity: usize) -> usize {
     }
 }
 
-/// `AtomicUsize` with 64-byte alignment for better performance.
+/// Checks the element layout recorded in a queue header against `T`.
+pub(crate) fn validate_element_layout<T>(size: usize, align: usize) -> Result<(), Error> {
+    let expected = Layout::new::<T>();
+    if size == expected.size() && align == expected.align() {
+        return Ok(());
+    }
+
+    let found = Layout::from_size_align(size, align).map_err(|_| Error::InvalidBufferSize)?;
+    Err(Error::ElementLayoutMismatch { expected, found })
+}
+
+/// Checks that `size` bytes of queue memory hold the `minimum` the queue's
+/// layout needs, so a short file is reported before any of it is read.
+pub(crate) fn validate_region_size(size: usize, minimum: usize) -> Result<(), Error> {
+    if size < minimum {
+        return Err(Error::FileTooSmall {
+            given: size,
+            minimum,
+        });
+    }
+
+    Ok(())
+}
+
+/// `AtomicUsize` padded to a cache line to avoid false sharing.
+///
+/// Apple Silicon and many ARM server cores use 128-byte cache lines, so
+/// those targets align to 128 bytes; everything else aligns to 64.
 #[derive(Default)]
-#[repr(C, align(64))]
+#[cfg_attr(any(target_arch = "aarch64", target_os = "macos"), repr(C, align(128)))]
+#[cfg_attr(
+    not(any(target_arch = "aarch64", target_os = "macos")),
+    repr(C, align(64))
+)]
 struct CacheAlignedAtomicSize {
     inner: AtomicUsize,
 }
@@ -4,8 +4,8 @@ use crate::{
     error::{Error, WaitError},
     futex::{Waiters, SPIN_ATTEMPTS},
     normalized_capacity,
-    shmem::Region,
-    CacheAlignedAtomicSize, VERSION,
+    shmem::{size_file, Region},
+    validate_element_layout, validate_region_size, CacheAlignedAtomicSize, VERSION,
 };
 use core::{
     iter::FusedIterator,
@@ -88,6 +88,19 @@ impl<T> Producer<T> {
         })
     }
 
+    /// Return the capacity of the queue in items.
+    pub fn capacity(&self) -> usize {
+        self.queue.capacity()
+    }
+
+    /// Return the size in bytes of the memory region backing the queue.
+    ///
+    /// This is the full file or allocation size, which may exceed
+    /// [`minimum_file_size`] for the queue's capacity; the excess is unused.
+    pub fn mapped_len(&self) -> usize {
+        self.queue.region.size()
+    }
+
     /// Writes item into the queue or returns it if there is not enough space.
     pub fn try_write(&self, item: T) -> Result<(), T> {
         // SAFETY: On successful reservation the item is written below.
@@ -273,6 +286,19 @@ impl<T> Consumer<T> {
         })
     }
 
+    /// Return the capacity of the queue in items.
+    pub fn capacity(&self) -> usize {
+        self.queue.capacity()
+    }
+
+    /// Return the size in bytes of the memory region backing the queue.
+    ///
+    /// This is the full file or allocation size, which may exceed
+    /// [`minimum_file_size`] for the queue's capacity; the excess is unused.
+    pub fn mapped_len(&self) -> usize {
+        self.queue.region.size()
+    }
+
     /// Attempts to read a value from the queue.
     /// Returns `None` if there are no values available.
     pub fn try_read(&self) -> Option<T> {
@@ -670,6 +696,8 @@ struct SharedQueueHeader {
     magic: AtomicU64,
     version: u32,
     buffer_mask: u32,
+    element_size: usize,
+    element_align: usize,
 
     /// Producer reservation cursor.
     ///
@@ -706,7 +734,9 @@ impl SharedQueueHeader {
     /// - The returned `region` must not be passed to any other queue-header
     ///   initialization routine.
     unsafe fn create<T>(file: &File, size: usize) -> Result<(Arc<Region>, NonNull<Self>), Error> {
-        file.set_len(size as u64)?;
+        // Reject sizes without room for a slot before touching the file.
+        validate_region_size(size, minimum_file_size::<T>(1))?;
+        size_file(file, size)?;
 
         let region = Region::map_file(file, size)?;
         // SAFETY: caller guarantees this mapping is initialized exactly once.
@@ -727,7 +757,7 @@ impl SharedQueueHeader {
         //         alignment of `SharedQueueHeader`.
         //         Access is exclusive because the caller guarantees this region
         //         is initialized at most once.
-        unsafe { Self::initialize(header, buffer_size_in_items) };
+        unsafe { Self::initialize::<T>(header, buffer_size_in_items) };
         Ok(header)
     }
 
@@ -752,18 +782,24 @@ impl SharedQueueHeader {
 
         let buffer_offset = Self::buffer_offset::<T>();
         if file_size < buffer_offset {
-            return Err(Error::InvalidBufferSize);
+            return Err(Error::FileTooSmall {
+                given: file_size,
+                minimum: minimum_file_size::<T>(1),
+            });
         }
 
         // The buffer size (in units of T) must be a power of two.
         let buffer_size_in_bytes = file_size - buffer_offset;
         let mut buffer_size_in_items = buffer_size_in_bytes / core::mem::size_of::<T>();
+        if buffer_size_in_items == 0 {
+            return Err(Error::ItemTooLarge {
+                item_size: core::mem::size_of::<T>(),
+                available: buffer_size_in_bytes,
+            });
+        }
         if !buffer_size_in_items.is_power_of_two() {
             // If not a power of two, round down to the previous power of two.
             buffer_size_in_items = buffer_size_in_items.next_power_of_two() >> 1;
-            if buffer_size_in_items == 0 {
-                return Err(Error::InvalidBufferSize);
-            }
         }
 
         // The buffer mask is stored as u32, so the capacity must fit.
@@ -780,7 +816,7 @@ impl SharedQueueHeader {
     /// - `header` must be non-null and properly aligned.
     /// - `header` allocation must be large enough to hold the header and the buffer.
     /// - `access` to `header` must be unique when this is called.
-    unsafe fn initialize(mut header_ptr: NonNull<Self>, buffer_size_in_items: usize) {
+    unsafe fn initialize<T>(mut header_ptr: NonNull<Self>, buffer_size_in_items: usize) {
         // SAFETY:
         // - `header` is non-null and aligned properly.
         // - `access` to `header` is unique.
@@ -791,18 +827,22 @@ impl SharedQueueHeader {
         header.consumer_release.store(0, Ordering::Release);
         header.waiters.initialize();
         header.buffer_mask = u32::try_from(buffer_size_in_items - 1).unwrap();
+        header.element_size = core::mem::size_of::<T>();
+        header.element_align = core::mem::align_of::<T>();
         header.version = VERSION;
         header.magic.store(MAGIC, Ordering::Release);
     }
 
     fn join<T>(file: &File) -> Result<(Arc<Region>, NonNull<Self>), Error> {
         let file_size = file.metadata()?.len() as usize;
+        validate_region_size(file_size, minimum_file_size::<T>(1))?;
         let region = Region::map_file(file, file_size)?;
         let header = Self::join_region::<T>(&region)?;
         Ok((region, header))
     }
 
     fn join_region<T>(region: &Arc<Region>) -> Result<NonNull<Self>, Error> {
+        validate_region_size(region.size(), minimum_file_size::<T>(1))?;
         let header = region.addr().cast::<Self>();
         {
             // SAFETY: The header is non-null and aligned properly.
@@ -819,7 +859,14 @@ impl SharedQueueHeader {
                     actual: header.version,
                 });
             }
+            validate_element_layout::<T>(header.element_size, header.element_align)?;
             let buffer_size_in_items = (header.buffer_mask as usize).wrapping_add(1);
+            // A file truncated after creation must not be read past its end.
+            let minimum = buffer_size_in_items
+                .checked_mul(core::mem::size_of::<T>())
+                .and_then(|buffer_size| buffer_size.checked_add(Self::buffer_offset::<T>()))
+                .ok_or(Error::InvalidBufferSize)?;
+            validate_region_size(region.size(), minimum)?;
             if buffer_size_in_items != Self::calculate_buffer_size_in_items::<T>(region.size())? {
                 return Err(Error::InvalidBufferSize);
             }
@@ -1623,6 +1670,133 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_capacity_is_shared_by_cloned_endpoints() {
+        for create_queue in test_queue_creators::<u64>() {
+            let (producer, consumer) = create_queue(16);
+            let (producer2, consumer2) = (producer.clone(), consumer.clone());
+
+            assert_eq!(producer.capacity(), 16);
+            assert_eq!(producer2.capacity(), 16);
+            assert_eq!(consumer.capacity(), 16);
+            assert_eq!(consumer2.capacity(), 16);
+        }
+    }
+
+    #[cfg(not(miri))]
+    #[test]
+    fn test_capacity_rounds_down_to_file_size() {
+        let file = create_temp_shmem_file().expect("failed to create temp file");
+        let file_size = minimum_file_size::<Item>(1000);
+        let producer =
+            unsafe { Producer::<Item>::create(&file, file_size) }.expect("failed to create");
+        let consumer = unsafe { Consumer::<Item>::join(&file) }.expect("failed to join");
+
+        // 1000 items round up to a 1024-item file; fewer bytes round down.
+        assert_eq!(producer.capacity(), 1024);
+        assert_eq!(consumer.capacity(), 1024);
+
+        let file = create_temp_shmem_file().expect("failed to create temp file");
+        let file_size = minimum_file_size::<Item>(1024) - core::mem::size_of::<Item>();
+        let producer =
+            unsafe { Producer::<Item>::create(&file, file_size) }.expect("failed to create");
+        assert_eq!(producer.capacity(), 512);
+    }
+
+    #[test]
+    fn test_mapped_len() {
+        for create_queue in test_queue_creators::<u64>() {
+            let (producer, consumer) = create_queue(64);
+
+            assert_eq!(producer.mapped_len(), minimum_file_size::<u64>(64));
+            assert_eq!(consumer.mapped_len(), minimum_file_size::<u64>(64));
+        }
+    }
+
+    #[test]
+    fn test_page_sized_item_is_too_large_for_one_page() {
+        // The cursor cache lines leave less than a page after the header.
+        type Item = [u8; 4096];
+        let page = crate::shmem::MINIMUM_REGION_ALIGNMENT;
+        let available = page - SharedQueueHeader::buffer_offset::<Item>();
+        assert!(matches!(
+            SharedQueueHeader::calculate_buffer_size_in_items::<Item>(page),
+            Err(Error::ItemTooLarge { item_size: 4096, available: a }) if a == available
+        ));
+    }
+
+    #[test]
+    fn test_region_without_a_slot_is_rejected() {
+        type Item = [u64; 4];
+        // Room for the header and its cursor cache lines, but no slot.
+        let size = SharedQueueHeader::buffer_offset::<Item>();
+        let region = Region::alloc(NonZeroUsize::new(size).unwrap()).expect("alloc");
+        assert!(matches!(
+            SharedQueueHeader::join_region::<Item>(&region),
+            Err(Error::FileTooSmall { given, minimum })
+                if given == size && minimum == size + size_of::<Item>()
+        ));
+
+        // Creating over less than the header is rejected too.
+        assert!(matches!(
+            SharedQueueHeader::calculate_buffer_size_in_items::<Item>(size - 1),
+            Err(Error::FileTooSmall { .. })
+        ));
+    }
+
+    #[test]
+    fn test_region_shorter_than_header_buffer_is_rejected() {
+        let (producer, _consumer) = pair::<u32>(64).expect("failed to create queue");
+        let region = &producer.queue.region;
+
+        // A view cut one slot short of the buffer the header describes.
+        let short = region.size() - size_of::<u32>();
+        let view = unsafe { Region::borrowed(region.addr(), short) }.expect("borrow");
+        assert!(matches!(
+            SharedQueueHeader::join_region::<u32>(&view),
+            Err(Error::FileTooSmall { given, minimum })
+                if given == short && minimum == region.size()
+        ));
+    }
+
+    #[cfg(not(miri))]
+    #[test]
+    fn test_create_leaves_file_alone_when_no_slot_fits() {
+        let file = create_temp_shmem_file().expect("failed to create temp file");
+        file.set_len(16).expect("failed to size file");
+
+        // Past the header, but a few bytes short of one slot.
+        let size = SharedQueueHeader::buffer_offset::<u64>() + 4;
+        assert!(matches!(
+            unsafe { Producer::<u64>::create(&file, size) },
+            Err(Error::FileTooSmall { given, minimum })
+                if given == size && minimum == minimum_file_size::<u64>(1)
+        ));
+        assert_eq!(file.metadata().unwrap().len(), 16);
+    }
+
+    #[test]
+    fn test_join_rejects_element_layout_mismatch() {
+        type Item = [u16; 4];
+        let (producer, _consumer) = pair::<Item>(64).expect("failed to create queue");
+        let region = &producer.queue.region;
+        let recorded = std::alloc::Layout::new::<Item>();
+
+        // Same size, stricter alignment.
+        assert!(matches!(
+            SharedQueueHeader::join_region::<u64>(region),
+            Err(Error::ElementLayoutMismatch { expected, found })
+                if expected == std::alloc::Layout::new::<u64>() && found == recorded
+        ));
+
+        // Same alignment, different size, even though it divides the buffer.
+        assert!(matches!(
+            SharedQueueHeader::join_region::<[u16; 2]>(region),
+            Err(Error::ElementLayoutMismatch { expected, found })
+                if expected == std::alloc::Layout::new::<[u16; 2]>() && found == recorded
+        ));
+    }
+
     #[test]
     fn test_multiple_producers_consumers() {
         for create_queue in test_queue_creators::<Item>() {
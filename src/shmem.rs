@@ -3,6 +3,20 @@ use std::{fs::File, num::NonZeroUsize, ptr::NonNull, sync::Arc};
 
 pub(crate) const MINIMUM_REGION_ALIGNMENT: usize = 4096;
 
+/// Memory usage hint for a queue's mapping, applied with `madvise`.
+///
+/// Hints apply only to the calling process's view of the memory.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Advice {
+    /// Expect sequential access, so the kernel can read ahead aggressively.
+    Sequential,
+    /// Expect access soon, so the kernel can fault pages in ahead of time.
+    WillNeed,
+    /// Back the mapping with transparent huge pages where possible.
+    #[cfg(target_os = "linux")]
+    HugePage,
+}
+
 pub(crate) struct Region {
     addr: NonNull<u8>,
     size: usize,
@@ -38,6 +52,20 @@ impl Region {
         }))
     }
 
+    /// Wraps caller-owned memory. Dropping the region leaves it untouched.
+    ///
+    /// # Safety
+    /// - `addr` must be valid for reads and writes of `size` bytes for as
+    ///   long as the region is alive.
+    pub(crate) unsafe fn borrowed(addr: NonNull<u8>, size: usize) -> Result<Arc<Self>, Error> {
+        validate_region_alignment(addr)?;
+        Ok(Arc::new(Self {
+            addr,
+            size,
+            backing: RegionBacking::Borrowed,
+        }))
+    }
+
     pub(crate) fn addr(&self) -> NonNull<u8> {
         self.addr
     }
@@ -49,6 +77,38 @@ impl Region {
     pub(crate) fn is_heap(&self) -> bool {
         matches!(self.backing, RegionBacking::Heap(_))
     }
+
+    /// Applies `advice` to the whole region if it is a file mapping.
+    ///
+    /// Heap and borrowed regions are not page-granular ranges this region
+    /// owns, so advising them could affect neighbouring memory; they are
+    /// left alone.
+    #[cfg(unix)]
+    pub(crate) fn advise(&self, advice: Advice) -> Result<(), Error> {
+        if !matches!(self.backing, RegionBacking::MappedFile) {
+            return Ok(());
+        }
+        let advice = match advice {
+            Advice::Sequential => libc::MADV_SEQUENTIAL,
+            Advice::WillNeed => libc::MADV_WILLNEED,
+            #[cfg(target_os = "linux")]
+            Advice::HugePage => libc::MADV_HUGEPAGE,
+        };
+        // SAFETY: the region is a live mapping of `size` bytes made by
+        //         `map_file`; these hints never change its contents.
+        let result = unsafe { libc::madvise(self.addr.as_ptr().cast(), self.size, advice) };
+        if result != 0 {
+            return Err(Error::Madvise(std::io::Error::last_os_error()));
+        }
+
+        Ok(())
+    }
+
+    /// Hints are not supported here, so this does nothing.
+    #[cfg(windows)]
+    pub(crate) fn advise(&self, _advice: Advice) -> Result<(), Error> {
+        Ok(())
+    }
 }
 
 impl Drop for Region {
@@ -62,6 +122,7 @@ impl Drop for Region {
                 // SAFETY: addr was allocated with this exact layout in `alloc`.
                 unsafe { std::alloc::dealloc(self.addr.as_ptr(), layout) };
             }
+            RegionBacking::Borrowed => {}
         }
     }
 }
@@ -69,6 +130,7 @@ impl Drop for Region {
 enum RegionBacking {
     MappedFile,
     Heap(std::alloc::Layout),
+    Borrowed,
 }
 
 // SAFETY: The mapped memory is shared (MAP_SHARED / file-backed) and access
@@ -88,6 +150,51 @@ fn validate_region_alignment(addr: NonNull<u8>) -> Result<(), Error> {
     Ok(())
 }
 
+/// Creates an anonymous in-memory file to back a queue, so no filesystem
+/// path is needed.
+///
+/// The file starts empty; pass it to a queue's `create` to size and
+/// initialize it. It can be shared with another process by sending its
+/// descriptor (e.g. with `SCM_RIGHTS`) and joined there from the received
+/// descriptor. The descriptor is close-on-exec.
+#[cfg(target_os = "linux")]
+pub fn memfd_create(name: &core::ffi::CStr) -> Result<File, Error> {
+    use std::os::fd::FromRawFd;
+
+    // SAFETY: `name` is a valid NUL-terminated string.
+    let fd = unsafe { libc::memfd_create(name.as_ptr(), libc::MFD_CLOEXEC) };
+    if fd < 0 {
+        return Err(Error::Io(std::io::Error::last_os_error()));
+    }
+
+    // SAFETY: `fd` is a freshly created descriptor owned by nothing else.
+    Ok(unsafe { File::from_raw_fd(fd) })
+}
+
+/// Sizes `file` to exactly `size` bytes for a newly created queue.
+///
+/// On Linux the storage is also reserved with `posix_fallocate`, so running
+/// out of space (e.g. on a full tmpfs) fails here instead of raising `SIGBUS`
+/// when a page of the mapping is first touched. Filesystems that cannot
+/// preallocate keep the plain truncation.
+pub(crate) fn size_file(file: &File, size: usize) -> Result<(), Error> {
+    file.set_len(size as u64).map_err(Error::Truncate)?;
+
+    #[cfg(target_os = "linux")]
+    {
+        use std::os::fd::AsRawFd;
+
+        let len = libc::off_t::try_from(size).map_err(|_| Error::InvalidBufferSize)?;
+        // SAFETY: the descriptor is valid for the lifetime of `file`.
+        match unsafe { libc::posix_fallocate(file.as_raw_fd(), 0, len) } {
+            0 | libc::EOPNOTSUPP | libc::EINVAL => {}
+            err => return Err(Error::Truncate(std::io::Error::from_raw_os_error(err))),
+        }
+    }
+
+    Ok(())
+}
+
 /// Maps a file into memory.
 #[cfg(unix)]
 fn map_file(file: &File, size: usize) -> Result<NonNull<u8>, Error> {
@@ -213,6 +320,43 @@ pub(crate) fn create_temp_shmem_file() -> Result<File, Error> {
 mod tests {
     use super::*;
 
+    #[cfg(all(target_os = "linux", not(miri)))]
+    #[test]
+    fn test_memfd_backed_queue() {
+        use crate::spsc::{minimum_file_size, Consumer, Producer};
+        use std::os::fd::{AsRawFd, FromRawFd};
+
+        let file = memfd_create(c"shaq-test").unwrap();
+        let mut producer =
+            unsafe { Producer::<u64>::create(&file, minimum_file_size::<u64>(8)) }.unwrap();
+
+        // Join from a duplicated descriptor, as a process receiving it would.
+        let fd = unsafe { libc::dup(file.as_raw_fd()) };
+        assert!(fd >= 0);
+        let received = unsafe { File::from_raw_fd(fd) };
+        let mut consumer = unsafe { Consumer::<u64>::join(&received) }.unwrap();
+
+        producer.push(42).unwrap();
+        consumer.sync();
+        assert_eq!(consumer.pop(), Some(42));
+    }
+
+    #[cfg(not(miri))]
+    #[test]
+    fn test_size_file_sets_exact_len() {
+        let file = create_temp_shmem_file().expect("temp file");
+        size_file(&file, 3 * MINIMUM_REGION_ALIGNMENT).expect("grow");
+        assert_eq!(
+            file.metadata().unwrap().len(),
+            3 * MINIMUM_REGION_ALIGNMENT as u64
+        );
+        size_file(&file, MINIMUM_REGION_ALIGNMENT).expect("shrink");
+        assert_eq!(
+            file.metadata().unwrap().len(),
+            MINIMUM_REGION_ALIGNMENT as u64
+        );
+    }
+
     #[cfg(not(miri))]
     #[test]
     fn test_region_is_minimum_region_aligned() {
@@ -240,4 +384,12 @@ mod tests {
         assert_eq!(region.addr().align_offset(MINIMUM_REGION_ALIGNMENT), 0);
         assert_eq!(region.size(), MINIMUM_REGION_ALIGNMENT + 1);
     }
+
+    #[test]
+    fn test_advise_leaves_heap_region_alone() {
+        // Not a page multiple, so madvise would spill past the allocation.
+        let region = Region::alloc(NonZeroUsize::new(MINIMUM_REGION_ALIGNMENT + 1).unwrap())
+            .expect("allocation failed");
+        region.advise(Advice::WillNeed).expect("advise is a no-op");
+    }
 }
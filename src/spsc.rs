@@ -2,23 +2,63 @@ use crate::{
     error::{Error, WaitError},
     futex::{Waiters, SPIN_ATTEMPTS},
     normalized_capacity,
-    shmem::Region,
-    CacheAlignedAtomicSize, VERSION,
+    shmem::{size_file, Advice, Region},
+    validate_element_layout, validate_region_size, CacheAlignedAtomicSize, VERSION,
 };
-use core::{marker::PhantomData, ptr::NonNull};
+use core::{
+    cell::Cell, iter::FusedIterator, marker::PhantomData, mem::MaybeUninit, ops::Index,
+    ptr::NonNull,
+};
+#[cfg(target_os = "linux")]
+use std::os::fd::RawFd;
 use std::{
     fs::File,
     num::NonZeroUsize,
     sync::{
-        atomic::{AtomicU64, Ordering},
+        atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering},
         Arc,
     },
     time::Duration,
 };
 
+mod bytes;
+
+pub use bytes::{byte_pair, ByteConsumer, ByteProducer};
+
 /// Unique identifier for SPSC queue in shared memory.
 const MAGIC: u64 = u64::from_be_bytes(*b"shaqspsc");
 
+/// Byte offset of the native-endian `u64` magic, whose value is
+/// `u64::from_be_bytes(*b"shaqspsc")`, from the start of the file.
+///
+/// These offsets describe the on-disk layout for external tooling. They are
+/// only meaningful for a file whose version matches this build, and for the
+/// target it was built for: the write and read positions each sit on their
+/// own cache line, aligned to 128 bytes on aarch64 and macOS and to 64 bytes
+/// elsewhere, so [`WRITE_OFFSET`], [`READ_OFFSET`] and [`buffer_offset`]
+/// differ between targets at the same version. Tooling must be built for the
+/// same target as the processes sharing the file.
+pub const MAGIC_OFFSET: usize = core::mem::offset_of!(SharedQueueHeader, magic);
+/// Byte offset of the `u32` format version.
+pub const VERSION_OFFSET: usize = core::mem::offset_of!(SharedQueueHeader, version);
+/// Byte offset of the `u32` buffer mask, one less than the capacity in items.
+pub const BUFFER_MASK_OFFSET: usize = core::mem::offset_of!(SharedQueueHeader, buffer_mask);
+/// Byte offset of the `usize` committed write position.
+pub const WRITE_OFFSET: usize = core::mem::offset_of!(SharedQueueHeader, write);
+/// Byte offset of the `usize` finalized read position.
+pub const READ_OFFSET: usize = core::mem::offset_of!(SharedQueueHeader, read);
+
+/// Returns the byte offset of the first element from the start of the file.
+///
+/// Element `i` of the position space lives at
+/// `buffer_offset::<T>() + (i & mask) * size_of::<T>()`.
+pub const fn buffer_offset<T>() -> usize {
+    SharedQueueHeader::buffer_offset::<T>()
+}
+
+/// Maximum length of the application metadata stored in a queue header.
+pub const METADATA_CAPACITY: usize = 64;
+
 /// Calculates the minimum file size required for a queue with given capacity.
 /// Note that file size MAY need to be increased beyond this to account for
 /// page-size requirements.
@@ -40,7 +80,7 @@ pub fn pair<T: Send>(capacity: usize) -> Result<(Producer<T>, Consumer<T>), Erro
     let region_size = minimum_region_size::<T>(capacity);
     let region = Region::alloc(NonZeroUsize::new(region_size).ok_or(Error::InvalidBufferSize)?)?;
     // SAFETY: `region` is freshly allocated and used only for this queue.
-    let header = unsafe { SharedQueueHeader::create_in_region::<T>(&region) }?;
+    let header = unsafe { SharedQueueHeader::create_in_region::<T>(&region, &[]) }?;
     let producer = unsafe { Producer::from_header(Arc::clone(&region), header) }?;
     let consumer = unsafe { Consumer::from_header(region, header) }?;
     Ok((producer, consumer))
@@ -49,6 +89,17 @@ pub fn pair<T: Send>(capacity: usize) -> Result<(Producer<T>, Consumer<T>), Erro
 /// Producer side of the SPSC shared queue.
 pub struct Producer<T> {
     queue: SharedQueue<T>,
+    /// Checks made by blocking writes before parking on the futex.
+    spin_attempts: usize,
+    /// Reservations [`Producer::commit`] lets accumulate before publishing.
+    commit_interval: NonZeroUsize,
+    /// Write position last requested by [`Producer::commit`].
+    commit_requested: Cell<usize>,
+    /// Write position last published to the header.
+    committed: Cell<usize>,
+    /// Caller-owned `eventfd` signaled after every commit.
+    #[cfg(target_os = "linux")]
+    eventfd: Option<RawFd>,
 }
 
 impl<T> Producer<T> {
@@ -69,7 +120,30 @@ impl<T> Producer<T> {
         // SAFETY: caller guarantees this process or thread is the externally
         // designated sole initializer, so initializing the queue header for
         // this mapping happens exactly once.
-        let (region, header) = unsafe { SharedQueueHeader::create::<T>(file, file_size) }?;
+        let (region, header) = unsafe { SharedQueueHeader::create::<T>(file, file_size, &[]) }?;
+        // SAFETY: `header` is non-null and aligned properly and allocated with
+        //         size of `file_size`.
+        unsafe { Self::from_header(region, header) }
+    }
+
+    /// Creates a new producer like [`Self::create`], and stores `metadata`
+    /// in the queue header for consumers to read on join.
+    ///
+    /// The metadata is written before the queue is published and never
+    /// changes afterwards. It may be up to [`METADATA_CAPACITY`] bytes long.
+    ///
+    /// # Safety
+    /// Same as [`Self::create`].
+    pub unsafe fn create_with_metadata(
+        file: &File,
+        file_size: usize,
+        metadata: &[u8],
+    ) -> Result<Self, Error> {
+        // SAFETY: caller guarantees this process or thread is the externally
+        // designated sole initializer, so initializing the queue header for
+        // this mapping happens exactly once.
+        let (region, header) =
+            unsafe { SharedQueueHeader::create::<T>(file, file_size, metadata) }?;
         // SAFETY: `header` is non-null and aligned properly and allocated with
         //         size of `file_size`.
         unsafe { Self::from_header(region, header) }
@@ -92,6 +166,46 @@ impl<T> Producer<T> {
         unsafe { Self::from_header(region, header) }
     }
 
+    /// Creates a new producer for a shared queue in caller-provided memory.
+    ///
+    /// This is for memory obtained outside shaq, such as a mapping made by
+    /// the caller. The memory must be aligned to 4096 bytes.
+    ///
+    /// # Safety
+    /// - `addr` must be valid for reads and writes of `size` bytes until this
+    ///   queue and every endpoint joined from it are dropped.
+    /// - The memory must not be accessed other than through queue endpoints.
+    /// - The same requirements as [`Self::create`] apply to the memory in
+    ///   place of the file.
+    pub unsafe fn create_raw(addr: NonNull<u8>, size: usize) -> Result<Self, Error> {
+        // SAFETY: caller guarantees the memory is valid and outlives the queue.
+        let region = unsafe { Region::borrowed(addr, size) }?;
+        // SAFETY: caller guarantees this process or thread is the externally
+        // designated sole initializer of this memory.
+        let header = unsafe { SharedQueueHeader::create_in_region::<T>(&region, &[]) }?;
+        // SAFETY: `header` is non-null and aligned properly and allocated with
+        //         size of `size`.
+        unsafe { Self::from_header(region, header) }
+    }
+
+    /// Joins an existing producer for a shared queue in caller-provided memory
+    /// that was initialized by [`Producer::create_raw`] or
+    /// [`Consumer::create_raw`].
+    ///
+    /// # Safety
+    /// - `addr` must be valid for reads and writes of `size` bytes until this
+    ///   queue and every endpoint joined from it are dropped.
+    /// - The same requirements as [`Self::join`] apply to the memory in place
+    ///   of the file.
+    pub unsafe fn join_raw(addr: NonNull<u8>, size: usize) -> Result<Self, Error> {
+        // SAFETY: caller guarantees the memory is valid and outlives the queue.
+        let region = unsafe { Region::borrowed(addr, size) }?;
+        let header = SharedQueueHeader::join_region::<T>(&region)?;
+        // SAFETY: `header` is non-null and aligned properly and allocated with
+        //         size of `size`.
+        unsafe { Self::from_header(region, header) }
+    }
+
     /// Creates a Consumer that shares the same memory mapping.
     ///
     /// # Safety
@@ -108,11 +222,23 @@ impl<T> Producer<T> {
         region: Arc<Region>,
         header: NonNull<SharedQueueHeader>,
     ) -> Result<Self, Error> {
+        // SAFETY:
+        // - `header` is non-null and aligned properly.
+        // - allocation at `header` is large enough to hold the header and the buffer.
+        let queue = unsafe { SharedQueue::from_header(region, header) }?;
+        queue
+            .header()
+            .producer_pid
+            .store(std::process::id(), Ordering::Relaxed);
+        let write = queue.cached_write;
         Ok(Self {
-            // SAFETY:
-            // - `header` is non-null and aligned properly.
-            // - allocation at `header` is large enough to hold the header and the buffer.
-            queue: unsafe { SharedQueue::from_header(region, header) }?,
+            queue,
+            spin_attempts: SPIN_ATTEMPTS,
+            commit_interval: NonZeroUsize::MIN,
+            commit_requested: Cell::new(write),
+            committed: Cell::new(write),
+            #[cfg(target_os = "linux")]
+            eventfd: None,
         })
     }
 
@@ -121,16 +247,70 @@ impl<T> Producer<T> {
         self.queue.capacity()
     }
 
-    /// Return the current length of the queue.
+    /// Return the size in bytes of the memory region backing the queue.
+    ///
+    /// This is the full file or allocation size, which may exceed
+    /// [`minimum_file_size`] for the queue's capacity; the excess is unused.
+    pub fn mapped_len(&self) -> usize {
+        self.queue.region.size()
+    }
+
+    /// Returns the application metadata stored when the queue was created,
+    /// empty if none was given.
+    pub fn metadata(&self) -> &[u8] {
+        self.queue.metadata()
+    }
+
+    /// Returns a snapshot of the queue's lifetime counters.
+    ///
+    /// The counters are read independently with relaxed ordering, so a
+    /// snapshot taken while the queue is in use may be slightly inconsistent.
+    pub fn stats(&self) -> QueueStats {
+        self.queue.stats()
+    }
+
+    /// Applies a memory usage hint to the queue's memory in this process.
+    ///
+    /// Hints are best-effort on the kernel's side; an error means the hint
+    /// was rejected, and callers that only want best-effort can ignore it.
+    /// This does nothing for heap-backed or caller-provided memory, or on
+    /// Windows.
+    pub fn advise(&self, advice: Advice) -> Result<(), Error> {
+        self.queue.region.advise(advice)
+    }
+
+    /// Return the number of items written but not yet known to be read.
+    ///
+    /// This reflects the producer's cached read position, so it may overstate
+    /// the length unless [`Self::sync`] was just called.
     pub fn len(&self) -> usize {
         self.queue.len()
     }
 
     /// Returns true if the queue is empty.
+    ///
+    /// See [`Self::len`] for staleness.
     pub fn is_empty(&self) -> bool {
         self.queue.is_empty()
     }
 
+    /// Return the number of slots that can be reserved before the queue is
+    /// full.
+    ///
+    /// This is a lower bound: it uses the cached read position, and the
+    /// consumer may have advanced further. Call [`Self::sync`] first to
+    /// refresh it.
+    pub fn remaining_capacity(&self) -> usize {
+        self.queue.capacity() - self.queue.len()
+    }
+
+    /// Returns true if no slot can be reserved.
+    ///
+    /// See [`Self::remaining_capacity`] for staleness.
+    pub fn is_full(&self) -> bool {
+        self.remaining_capacity() == 0
+    }
+
     /// Writes item into the queue or returns it if there is not enough space.
     pub fn try_write(&mut self, item: T) -> Result<(), T> {
         // SAFETY: pointer is written below if successfully reserved.
@@ -153,6 +333,20 @@ impl<T> Producer<T> {
     /// All reserved positions must be fully initialized before calling `commit`.
     /// Pointers should be dropped before calling `commit`.
     pub unsafe fn reserve(&mut self) -> Option<NonNull<T>> {
+        // SAFETY: caller upholds the requirements of `reserve`.
+        let reserved = unsafe { self.reserve_uncounted() };
+        if reserved.is_none() {
+            self.queue.record_full();
+        }
+        reserved
+    }
+
+    /// [`Self::reserve`] without counting a full queue, for retry loops that
+    /// count once per call instead.
+    ///
+    /// # Safety
+    /// Same as [`Self::reserve`].
+    unsafe fn reserve_uncounted(&mut self) -> Option<NonNull<T>> {
         // If write is > read + buffer_mask, the queue is written one iteration
         // ahead of the consumer, and we cannot reserve more space.
         if self.queue.cached_write.wrapping_sub(self.queue.cached_read) > self.queue.buffer_mask {
@@ -167,16 +361,170 @@ impl<T> Producer<T> {
         Some(reserved_ptr)
     }
 
+    /// Reserves up to `max` contiguous positions, and increments the cached
+    /// write position by the returned length.
+    ///
+    /// The run stops at the end of the ring and at the cached read position,
+    /// so it may be shorter than `max` (or empty) even when more space is
+    /// available after wrapping or syncing.
+    ///
+    /// # Safety
+    /// All reserved positions must be fully initialized before calling `commit`.
+    /// The slice should be dropped before calling `commit`.
+    pub unsafe fn reserve_slice(&mut self, max: usize) -> &mut [MaybeUninit<T>] {
+        let start = self.queue.mask(self.queue.cached_write);
+        let len = max
+            .min(self.remaining_capacity())
+            .min(self.queue.capacity() - start);
+        if len == 0 && max > 0 {
+            self.queue.record_full();
+        }
+        self.queue.cached_write = self.queue.cached_write.wrapping_add(len);
+
+        // SAFETY: `start..start + len` lies within the buffer and is reserved
+        //         for writing by this producer.
+        unsafe {
+            core::slice::from_raw_parts_mut(self.queue.buffer.as_ptr().add(start).cast(), len)
+        }
+    }
+
+    /// Reserves a slot for writing.
+    /// The slot, and any previously reserved slots, are committed when the
+    /// guard is dropped.
+    ///
+    /// Returns `None` if the queue is full. Leaking the guard leaves the slot
+    /// reserved but uncommitted, as with [`Self::reserve`].
+    ///
+    /// # Safety
+    /// - The caller must leave the reserved slot initialized with a valid `T`
+    ///   before the guard is dropped.
+    #[must_use]
+    pub unsafe fn try_reserve_write(&mut self) -> Option<WriteGuard<'_, T>> {
+        // SAFETY: the caller initializes the slot before the guard commits it.
+        let cell = unsafe { self.reserve() }?;
+        Some(WriteGuard {
+            producer: self,
+            cell,
+        })
+    }
+
+    /// Reserves exactly `count` consecutive slots for writing.
+    /// The slots, and any previously reserved slots, are committed when the
+    /// batch is dropped.
+    ///
+    /// Returns `None` if fewer than `count` slots are free.
+    ///
+    /// # Safety
+    /// - The caller must leave every reserved slot initialized with a valid `T`
+    ///   before the batch is dropped.
+    #[must_use]
+    pub unsafe fn try_reserve_write_batch(
+        &mut self,
+        count: NonZeroUsize,
+    ) -> Option<WriteBatch<'_, T>> {
+        if count.get() > self.remaining_capacity() {
+            return None;
+        }
+
+        let start = self.queue.cached_write;
+        self.queue.cached_write = start.wrapping_add(count.get());
+        Some(WriteBatch {
+            producer: self,
+            start,
+            count,
+        })
+    }
+
     /// Commits the reserved position, making it visible to the consumer.
+    ///
+    /// Every position reserved since the last commit is published by the one
+    /// Release store, so writes to all of them are visible to a consumer
+    /// that syncs afterwards.
+    ///
+    /// With a [`Self::set_commit_interval`] above 1, the store is skipped
+    /// until that many reservations are pending; skipped positions are
+    /// published by a later commit, by [`Self::flush`], or when the producer
+    /// is dropped.
     pub fn commit(&self) {
+        self.commit_requested.set(self.queue.cached_write);
+        if self.queue.cached_write.wrapping_sub(self.committed.get()) >= self.commit_interval.get()
+        {
+            self.publish_write(self.queue.cached_write);
+        }
+    }
+
+    /// Publishes the write position immediately, regardless of
+    /// [`Self::set_commit_interval`].
+    pub fn flush(&self) {
+        self.commit_requested.set(self.queue.cached_write);
+        if self.queue.cached_write != self.committed.get() {
+            self.publish_write(self.queue.cached_write);
+        }
+    }
+
+    /// Sets how many reservations [`Self::commit`] lets accumulate before it
+    /// publishes the write position. Defaults to 1, publishing on every call.
+    ///
+    /// Fewer publications mean fewer Release stores and wakeups for bursty
+    /// writers, at the cost of latency: committed items stay invisible to the
+    /// consumer until the interval fills or [`Self::flush`] is called.
+    /// Reservations still check the real full condition against the
+    /// unpublished write position, so deferred items are never overwritten.
+    /// An interval larger than the capacity can fill the queue with deferred
+    /// items, so [`Self::wait_writable_timeout`] and [`Self::reserve_timeout`]
+    /// publish them before waiting for the consumer to free space.
+    pub fn set_commit_interval(&mut self, interval: NonZeroUsize) {
+        self.commit_interval = interval;
+    }
+
+    /// Publishes the positions passed to [`Self::commit`] but deferred by
+    /// [`Self::set_commit_interval`], leaving later reservations unpublished.
+    fn publish_deferred(&self) {
+        if self.commit_requested.get() != self.committed.get() {
+            self.publish_write(self.commit_requested.get());
+        }
+    }
+
+    fn publish_write(&self, position: usize) {
+        self.committed.set(position);
         let header = self.queue.header();
         // Release publication; `wake` supplies the fence that pairs it with
         // a registering waiter and must be called unconditionally; see the
         // `futex` module docs.
-        header
-            .write
-            .store(self.queue.cached_write, Ordering::Release);
+        header.write.store(position, Ordering::Release);
         header.waiters.wake(&header.write, 1);
+        self.queue.record_fill(position);
+
+        #[cfg(target_os = "linux")]
+        if let Some(eventfd) = self.eventfd {
+            signal_eventfd(eventfd);
+        }
+    }
+
+    /// Sets an `eventfd` that [`Self::commit`] signals after publishing, so a
+    /// consumer can wait for the queue in an epoll loop alongside other
+    /// descriptors; see [`Consumer::set_readiness_fd`].
+    ///
+    /// The descriptor is not owned by the producer: the caller must keep it
+    /// open while it is set, and close it afterwards.
+    #[cfg(target_os = "linux")]
+    pub fn set_eventfd(&mut self, eventfd: Option<RawFd>) {
+        self.eventfd = eventfd;
+    }
+
+    /// Commits only the first `count` positions reserved since the last
+    /// commit, and rewinds the cached write position so the rest can be
+    /// reserved again.
+    ///
+    /// `count` is clamped to the number of uncommitted reservations. Rewound
+    /// positions are never made visible to the consumer. Positions already
+    /// passed to [`Self::commit`] are kept and published along with the
+    /// first `count`, even if [`Self::set_commit_interval`] deferred them.
+    pub fn commit_upto(&mut self, count: usize) {
+        let committed = self.commit_requested.get();
+        let count = count.min(self.queue.cached_write.wrapping_sub(committed));
+        self.queue.cached_write = committed.wrapping_add(count);
+        self.flush();
     }
 
     /// Synchronize the producer's cached read position with the queue's read
@@ -184,13 +532,344 @@ impl<T> Producer<T> {
     pub fn sync(&mut self) {
         self.queue.load_read();
     }
+
+    /// Marks the queue closed, telling the consumer no further items will be
+    /// committed.
+    ///
+    /// Items committed before this call remain readable; commits deferred by
+    /// [`Self::set_commit_interval`] are flushed first. Closing does not
+    /// wake a consumer blocked in a timed read, so a consumer waiting for
+    /// close should use bounded timeouts and check [`Consumer::is_finished`]
+    /// between them.
+    pub fn close(&self) {
+        self.flush();
+        self.queue.header().closed.store(true, Ordering::Release);
+    }
+
+    /// Resets [`QueueStats::high_water_mark`] to zero, e.g. to start a new
+    /// measurement window.
+    pub fn reset_high_water_mark(&self) {
+        self.queue
+            .header()
+            .high_water_mark
+            .store(0, Ordering::Relaxed);
+    }
+
+    /// Records that the producer is alive, for [`Consumer::producer_heartbeat_within`].
+    ///
+    /// This reads the clock, so call it periodically rather than on every
+    /// commit.
+    #[cfg(unix)]
+    pub fn heartbeat(&self) {
+        self.queue
+            .header()
+            .producer_heartbeat
+            .store(monotonic_nanos(), Ordering::Relaxed);
+    }
+
+    /// Returns whether the consumer called [`Consumer::heartbeat`] within the
+    /// last `timeout`.
+    ///
+    /// This is advisory and time-based: a live but stalled consumer looks
+    /// dead, and a consumer that never beats always does.
+    #[cfg(unix)]
+    pub fn consumer_heartbeat_within(&self, timeout: Duration) -> bool {
+        heartbeat_within(&self.queue.header().consumer_heartbeat, timeout)
+    }
+
+    /// Sets how many times blocking writes recheck the queue before parking
+    /// on the futex. Defaults to 2048.
+    ///
+    /// This mirrors [`Consumer::set_spin_attempts`] so both sides can be
+    /// tuned alike.
+    pub fn set_spin_attempts(&mut self, spin_attempts: usize) {
+        self.spin_attempts = spin_attempts;
+    }
+
+    /// Blocks until at least one position is free or `timeout` elapses.
+    ///
+    /// The thread parks on the queue's futex once a short spin finds no
+    /// space; [`Consumer::finalize`] wakes it. Pass [`Duration::MAX`] to wait
+    /// without a deadline, but note that a consumer which has exited never
+    /// frees space, so such a wait never returns.
+    pub fn wait_writable_timeout(&mut self, timeout: Duration) -> Result<(), WaitError> {
+        // The consumer cannot free space for items it has not seen.
+        self.publish_deferred();
+        let header = self.queue.header;
+        // SAFETY: `header` points to this producer's live shared queue header.
+        let header = unsafe { header.as_ref() };
+        header
+            .space_waiters
+            .wait_for(&header.read, self.spin_attempts, timeout, || {
+                self.queue.load_read();
+                if self.queue.len() <= self.queue.buffer_mask {
+                    Some(())
+                } else {
+                    None
+                }
+            })
+    }
+
+    /// Blocks until a position can be reserved or `timeout` elapses.
+    ///
+    /// A call that finds the queue full counts once towards
+    /// [`QueueStats::full`], however long it waits.
+    ///
+    /// # Safety
+    /// Same as [`Self::reserve`].
+    pub unsafe fn reserve_timeout(&mut self, timeout: Duration) -> Result<NonNull<T>, WaitError> {
+        self.queue.load_read();
+        // SAFETY: caller upholds the requirements of `reserve`.
+        if let Some(reserved) = unsafe { self.reserve() } {
+            return Ok(reserved);
+        }
+        // The consumer cannot free space for items it has not seen.
+        self.publish_deferred();
+        let header = self.queue.header;
+        // SAFETY: `header` points to this producer's live shared queue header.
+        let header = unsafe { header.as_ref() };
+        header
+            .space_waiters
+            .wait_for(&header.read, self.spin_attempts, timeout, || {
+                self.queue.load_read();
+                // SAFETY: caller upholds the requirements of `reserve`.
+                unsafe { self.reserve_uncounted() }
+            })
+    }
+}
+
+impl<T: Copy> Producer<T> {
+    /// Writes `value` and commits it, or returns it if the queue is full.
+    ///
+    /// The cached read position is synchronized first if the queue appears
+    /// full.
+    pub fn push(&mut self, value: T) -> Result<(), T> {
+        if self.is_full() {
+            self.sync();
+        }
+        self.try_write(value)?;
+        self.commit();
+        Ok(())
+    }
+
+    /// Writes as many of `items` as fit, in order, and commits them with a
+    /// single store. Returns the number written.
+    ///
+    /// The cached read position is synchronized first if `items` does not
+    /// appear to fit. A run that crosses the end of the ring is copied in two
+    /// parts.
+    pub fn push_slice(&mut self, items: &[T]) -> usize {
+        if self.remaining_capacity() < items.len() {
+            self.sync();
+        }
+
+        let mut written = 0;
+        // At most two runs: up to the end of the ring, then from its start.
+        while written < items.len() {
+            // SAFETY: every reserved position is written below before commit.
+            let run = unsafe { self.reserve_slice(items.len() - written) };
+            if run.is_empty() {
+                break;
+            }
+            let len = run.len();
+            // SAFETY: `run` holds `len` reserved positions, `MaybeUninit<T>`
+            //         has the layout of `T`, and the caller's slice cannot
+            //         overlap the queue's buffer.
+            unsafe {
+                core::ptr::copy_nonoverlapping(
+                    items[written..].as_ptr(),
+                    run.as_mut_ptr().cast::<T>(),
+                    len,
+                )
+            };
+            written += len;
+        }
+
+        if written > 0 {
+            self.commit();
+        }
+        written
+    }
+
+    /// Writes `value` and commits it, waiting up to `timeout` for space, or
+    /// returns it if the timeout elapses first.
+    ///
+    /// See [`Self::wait_writable_timeout`] for the waiting behavior.
+    pub fn push_timeout(&mut self, value: T, timeout: Duration) -> Result<(), T> {
+        if self.push(value).is_ok() {
+            return Ok(());
+        }
+        if self.wait_writable_timeout(timeout).is_err() {
+            return Err(value);
+        }
+        self.push(value)
+    }
 }
 
 unsafe impl<T: Send> Send for Producer<T> {}
 
+impl<T> Drop for Producer<T> {
+    /// Publishes any positions [`Producer::commit`] deferred under
+    /// [`Producer::set_commit_interval`]. Reservations that were never
+    /// committed stay unpublished.
+    fn drop(&mut self) {
+        self.publish_deferred();
+    }
+}
+
+/// Reads the system-wide monotonic clock, which every process on the host
+/// shares, in nanoseconds.
+#[cfg(unix)]
+fn monotonic_nanos() -> u64 {
+    let mut now = libc::timespec {
+        tv_sec: 0,
+        tv_nsec: 0,
+    };
+    // SAFETY: `now` is a valid timespec to write to.
+    unsafe { libc::clock_gettime(libc::CLOCK_MONOTONIC, &mut now) };
+    (now.tv_sec as u64)
+        .saturating_mul(1_000_000_000)
+        .saturating_add(now.tv_nsec as u64)
+}
+
+/// Returns whether `heartbeat` was stored within the last `timeout`.
+#[cfg(unix)]
+fn heartbeat_within(heartbeat: &AtomicU64, timeout: Duration) -> bool {
+    let last = heartbeat.load(Ordering::Relaxed);
+    let timeout = u64::try_from(timeout.as_nanos()).unwrap_or(u64::MAX);
+    last != 0 && monotonic_nanos().saturating_sub(last) <= timeout
+}
+
+/// Adds one to an `eventfd` counter.
+#[cfg(target_os = "linux")]
+fn signal_eventfd(eventfd: RawFd) {
+    let counter = 1u64;
+    // Only a saturated counter fails, and that descriptor is readable anyway,
+    // so the result is ignored.
+    // SAFETY: `counter` is a valid 8-byte buffer, the size an eventfd write
+    //         requires.
+    unsafe {
+        libc::write(
+            eventfd,
+            (&counter as *const u64).cast(),
+            core::mem::size_of::<u64>(),
+        )
+    };
+}
+
+#[must_use]
+/// A reservation for one logically uninitialized producer slot.
+///
+/// Initialize the slot with [`Self::write`] or through [`AsMut::as_mut`]
+/// before dropping the guard, which commits it.
+pub struct WriteGuard<'a, T> {
+    producer: &'a mut Producer<T>,
+    cell: NonNull<T>,
+}
+
+impl<T> core::convert::AsMut<MaybeUninit<T>> for WriteGuard<'_, T> {
+    /// Mutable reference to the reserved cell.
+    fn as_mut(&mut self) -> &mut MaybeUninit<T> {
+        let mut cell = self.cell.cast();
+        // SAFETY: The cell was reserved for writing.
+        unsafe { cell.as_mut() }
+    }
+}
+
+impl<T> WriteGuard<'_, T> {
+    /// Initializes the reserved slot; the guard commits it on drop.
+    ///
+    /// This performs a raw write. If the slot was already initialized through
+    /// [`AsMut::as_mut`], the previous value is overwritten without running
+    /// its destructor.
+    pub fn write(self, value: T) {
+        // SAFETY: The cell was reserved for writing.
+        unsafe { self.cell.write(value) };
+    }
+}
+
+impl<T> Drop for WriteGuard<'_, T> {
+    fn drop(&mut self) {
+        self.producer.commit();
+    }
+}
+
+#[must_use]
+/// A reservation for consecutive logically uninitialized producer slots.
+///
+/// The slots may wrap around the end of the ring, so they are accessed by
+/// index. Every slot must contain a valid `T` before the batch is dropped,
+/// which commits them.
+pub struct WriteBatch<'a, T> {
+    producer: &'a mut Producer<T>,
+    start: usize,
+    count: NonZeroUsize,
+}
+
+impl<T> WriteBatch<'_, T> {
+    pub fn len(&self) -> usize {
+        self.count.get()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        // count is guaranteed to be non-zero by the type, so this batch can never be empty.
+        false
+    }
+
+    fn cell(&self, index: usize) -> NonNull<T> {
+        debug_assert!(index < self.count.get());
+        let queue = &self.producer.queue;
+        let position = queue.mask(self.start.wrapping_add(index));
+        // SAFETY: The mask ensures the position is in bounds.
+        unsafe { queue.buffer.add(position) }
+    }
+
+    /// Returns a mutable reference to the reserved slot.
+    ///
+    /// Writing through the returned [`MaybeUninit`] does not drop a value
+    /// already in the slot.
+    ///
+    /// # Safety
+    /// - `index` must be less than [`Self::len`].
+    pub unsafe fn as_mut(&mut self, index: usize) -> &mut MaybeUninit<T> {
+        // SAFETY: The position was reserved for writing.
+        unsafe { self.cell(index).cast().as_mut() }
+    }
+
+    /// Writes a value into the slot at index.
+    ///
+    /// This performs a raw write and does not drop a value already in the slot.
+    ///
+    /// # Safety
+    /// - `index` must be less than [`Self::len`].
+    pub unsafe fn write(&mut self, index: usize, value: T) {
+        // SAFETY: The position was reserved for writing.
+        unsafe { self.cell(index).write(value) }
+    }
+}
+
+impl<T> Drop for WriteBatch<'_, T> {
+    fn drop(&mut self) {
+        self.producer.commit();
+    }
+}
+
 /// Consumer side of the SPSC shared queue.
 pub struct Consumer<T> {
     queue: SharedQueue<T>,
+    /// Checks made by blocking reads before parking on the futex.
+    spin_attempts: usize,
+    /// Header generation observed when this consumer attached.
+    generation: u64,
+    /// Reads [`Consumer::finalize`] lets accumulate before publishing.
+    finalize_interval: NonZeroUsize,
+    /// Read position last requested by [`Consumer::finalize`].
+    finalize_requested: usize,
+    /// Read position last published to the header.
+    finalized: usize,
+    /// Caller-owned `eventfd` the producer signals after every commit.
+    #[cfg(target_os = "linux")]
+    readiness_fd: Option<RawFd>,
 }
 
 impl<T> Consumer<T> {
@@ -211,7 +890,7 @@ impl<T> Consumer<T> {
         // SAFETY: caller guarantees this process or thread is the externally
         // designated sole initializer, so initializing the queue header for
         // this mapping happens exactly once.
-        let (region, header) = unsafe { SharedQueueHeader::create::<T>(file, file_size) }?;
+        let (region, header) = unsafe { SharedQueueHeader::create::<T>(file, file_size, &[]) }?;
         // SAFETY: `header` is non-null and aligned properly and allocated with
         //         size of `file_size`.
         unsafe { Self::from_header(region, header) }
@@ -234,6 +913,46 @@ impl<T> Consumer<T> {
         unsafe { Self::from_header(region, header) }
     }
 
+    /// Creates a new consumer for a shared queue in caller-provided memory.
+    ///
+    /// This is for memory obtained outside shaq, such as a mapping made by
+    /// the caller. The memory must be aligned to 4096 bytes.
+    ///
+    /// # Safety
+    /// - `addr` must be valid for reads and writes of `size` bytes until this
+    ///   queue and every endpoint joined from it are dropped.
+    /// - The memory must not be accessed other than through queue endpoints.
+    /// - The same requirements as [`Self::create`] apply to the memory in
+    ///   place of the file.
+    pub unsafe fn create_raw(addr: NonNull<u8>, size: usize) -> Result<Self, Error> {
+        // SAFETY: caller guarantees the memory is valid and outlives the queue.
+        let region = unsafe { Region::borrowed(addr, size) }?;
+        // SAFETY: caller guarantees this process or thread is the externally
+        // designated sole initializer of this memory.
+        let header = unsafe { SharedQueueHeader::create_in_region::<T>(&region, &[]) }?;
+        // SAFETY: `header` is non-null and aligned properly and allocated with
+        //         size of `size`.
+        unsafe { Self::from_header(region, header) }
+    }
+
+    /// Joins an existing consumer for a shared queue in caller-provided memory
+    /// that was initialized by [`Producer::create_raw`] or
+    /// [`Consumer::create_raw`].
+    ///
+    /// # Safety
+    /// - `addr` must be valid for reads and writes of `size` bytes until this
+    ///   queue and every endpoint joined from it are dropped.
+    /// - The same requirements as [`Self::join`] apply to the memory in place
+    ///   of the file.
+    pub unsafe fn join_raw(addr: NonNull<u8>, size: usize) -> Result<Self, Error> {
+        // SAFETY: caller guarantees the memory is valid and outlives the queue.
+        let region = unsafe { Region::borrowed(addr, size) }?;
+        let header = SharedQueueHeader::join_region::<T>(&region)?;
+        // SAFETY: `header` is non-null and aligned properly and allocated with
+        //         size of `size`.
+        unsafe { Self::from_header(region, header) }
+    }
+
     /// Creates a Producer that shares the same memory mapping.
     ///
     /// # Safety
@@ -250,11 +969,21 @@ impl<T> Consumer<T> {
         region: Arc<Region>,
         header: NonNull<SharedQueueHeader>,
     ) -> Result<Self, Error> {
+        // SAFETY:
+        // - `header` is non-null and aligned properly.
+        // - allocation at `header` is large enough to hold the header and the buffer.
+        let queue = unsafe { SharedQueue::from_header(region, header) }?;
+        let generation = queue.header().generation.load(Ordering::Acquire);
+        let read = queue.cached_read;
         Ok(Self {
-            // SAFETY:
-            // - `header` is non-null and aligned properly.
-            // - allocation at `header` is large enough to hold the header and the buffer.
-            queue: unsafe { SharedQueue::from_header(region, header) }?,
+            queue,
+            spin_attempts: SPIN_ATTEMPTS,
+            generation,
+            finalize_interval: NonZeroUsize::MIN,
+            finalize_requested: read,
+            finalized: read,
+            #[cfg(target_os = "linux")]
+            readiness_fd: None,
         })
     }
 
@@ -263,12 +992,50 @@ impl<T> Consumer<T> {
         self.queue.capacity()
     }
 
-    /// Return the current length of the queue.
+    /// Return the size in bytes of the memory region backing the queue.
+    ///
+    /// This is the full file or allocation size, which may exceed
+    /// [`minimum_file_size`] for the queue's capacity; the excess is unused.
+    pub fn mapped_len(&self) -> usize {
+        self.queue.region.size()
+    }
+
+    /// Returns the application metadata stored when the queue was created,
+    /// empty if none was given.
+    pub fn metadata(&self) -> &[u8] {
+        self.queue.metadata()
+    }
+
+    /// Returns a snapshot of the queue's lifetime counters.
+    ///
+    /// The counters are read independently with relaxed ordering, so a
+    /// snapshot taken while the queue is in use may be slightly inconsistent.
+    pub fn stats(&self) -> QueueStats {
+        self.queue.stats()
+    }
+
+    /// Applies a memory usage hint to the queue's memory in this process.
+    ///
+    /// Hints are best-effort on the kernel's side; an error means the hint
+    /// was rejected, and callers that only want best-effort can ignore it.
+    /// This does nothing for heap-backed or caller-provided memory, or on
+    /// Windows.
+    pub fn advise(&self, advice: Advice) -> Result<(), Error> {
+        self.queue.region.advise(advice)
+    }
+
+    /// Return the number of items available to read.
+    ///
+    /// This reflects the consumer's cached write position, so it may understate
+    /// the length unless [`Self::sync`] was just called. It does not change the
+    /// read position.
     pub fn len(&self) -> usize {
         self.queue.len()
     }
 
-    /// Returns true if the queue is empty.
+    /// Returns true if no items are available to read.
+    ///
+    /// See [`Self::len`] for staleness.
     pub fn is_empty(&self) -> bool {
         self.queue.is_empty()
     }
@@ -303,28 +1070,361 @@ impl<T> Consumer<T> {
         Some(read_ptr)
     }
 
-    /// Publishes the read position, making it visible to the producer.
-    /// All previously read items MUST be processed before this is called.
-    pub fn finalize(&mut self) {
-        self.queue
-            .header()
-            .read
-            .store(self.queue.cached_read, Ordering::Release);
+    /// Returns the next value, synchronizing and finalizing as needed.
+    /// Returns `None` if no values are available even after syncing.
+    ///
+    /// This is the convenience alternative to pairing [`Self::sync`],
+    /// [`Self::try_read_ptr`] and [`Self::finalize`] by hand. Calling `poll`
+    /// marks the value it previously returned as processed: the read position
+    /// is finalized on every call, subject to
+    /// [`Self::set_finalize_interval`], and flushed before syncing an empty
+    /// view, so the producer is never left waiting on space this consumer has
+    /// already read through.
+    pub fn poll(&mut self) -> Option<NonNull<T>> {
+        if self.queue.is_empty() {
+            self.flush();
+            self.sync();
+        } else {
+            self.finalize();
+        }
+        self.try_read_ptr()
+    }
+
+    /// Attempts to reserve a value from the queue, returning a guard.
+    /// Returns `None` if there are no values available.
+    ///
+    /// The read position, including any earlier unfinalized reads, is
+    /// finalized when the guard is dropped, so the value cannot be
+    /// overwritten while it is borrowed. The guard is also dropped during
+    /// unwinding, so a panic while it is held still finalizes.
+    #[must_use]
+    pub fn try_reserve_read(&mut self) -> Option<ReadGuard<'_, T>> {
+        let cell = self.try_read_ptr()?;
+        Some(ReadGuard {
+            consumer: self,
+            cell,
+        })
+    }
+
+    /// Returns an iterator over the values available when it is created.
+    ///
+    /// The write position is synchronized once up front, so the iterator
+    /// stops at that snapshot instead of chasing a live producer. The read
+    /// position is finalized once, when the iterator is dropped; values must
+    /// be processed and pointers discarded before then.
+    pub fn drain(&mut self) -> Drain<'_, T> {
+        self.sync();
+        Drain { consumer: self }
+    }
+
+    /// Returns a pointer to the next value without advancing the read
+    /// position, or `None` if there are no values available.
+    pub fn peek(&self) -> Option<NonNull<T>> {
+        self.peek_n(0)
+    }
+
+    /// Hints the CPU to start loading the next value into cache, so it is
+    /// resident by the time it is read.
+    ///
+    /// Does nothing if no further value is available at the cached write
+    /// position, or on targets other than x86-64.
+    #[inline]
+    pub fn prefetch_next(&self) {
+        #[cfg(target_arch = "x86_64")]
+        if let Some(next) = self.peek() {
+            use core::arch::x86_64::{_mm_prefetch, _MM_HINT_T0};
+            // SAFETY: a prefetch never faults and does not affect program
+            //         semantics; `next` is in bounds regardless.
+            unsafe { _mm_prefetch::<_MM_HINT_T0>(next.as_ptr().cast()) };
+        }
+    }
+
+    /// Returns a pointer to the value `n` positions past the next one without
+    /// advancing the read position, or `None` if fewer than `n + 1` values are
+    /// available.
+    pub fn peek_n(&self, n: usize) -> Option<NonNull<T>> {
+        if n >= self.queue.len() {
+            return None;
+        }
+
+        let index = self.queue.mask(self.queue.cached_read.wrapping_add(n));
+        // SAFETY: index is guaranteed to be within bounds given the mask.
+        Some(unsafe { self.queue.buffer.add(index) })
+    }
+
+    /// Reads up to `max` contiguous values from the queue, and increments the
+    /// cached read position by the returned length.
+    ///
+    /// The run stops at the end of the ring and at the cached write position,
+    /// so it may be shorter than `max` (or empty) even when more values are
+    /// available after wrapping or syncing.
+    ///
+    /// All read items should be processed before calling `finalize`.
+    pub fn read_slice(&mut self, max: usize) -> &[T] {
+        let start = self.queue.mask(self.queue.cached_read);
+        let len = max.min(self.queue.len()).min(self.queue.capacity() - start);
+        self.queue.cached_read = self.queue.cached_read.wrapping_add(len);
+
+        // SAFETY: `start..start + len` lies within the buffer and holds
+        //         committed items that the producer cannot overwrite until the
+        //         read position is finalized.
+        //         IF producer properly wrote items, or T is POD, it is safe to
+        //         convert to a slice here.
+        unsafe { core::slice::from_raw_parts(self.queue.buffer.as_ptr().add(start), len) }
+    }
+
+    /// Attempts to read up to `max` values from the queue.
+    /// Returns `None` if there are no values available.
+    ///
+    /// The values stay in the queue until [`Self::finalize`] publishes the
+    /// read position, so a whole batch is released with one store.
+    pub fn try_read_batch(&mut self, max: NonZeroUsize) -> Option<ReadBatch<'_, T>> {
+        let count = self.queue.len().min(max.get());
+        if count == 0 {
+            return None;
+        }
+
+        let start = self.queue.mask(self.queue.cached_read);
+        let first_len = count.min(self.queue.capacity() - start);
+        self.queue.cached_read = self.queue.cached_read.wrapping_add(count);
+
+        let buffer = self.queue.buffer.as_ptr();
+        // SAFETY: `start..start + first_len` and `0..count - first_len` lie
+        //         within the buffer and hold committed items that the producer
+        //         cannot overwrite until the read position is finalized.
+        //         IF producer properly wrote items, or T is POD, it is safe to
+        //         convert to slices here.
+        let (first, second) = unsafe {
+            (
+                core::slice::from_raw_parts(buffer.add(start), first_len),
+                core::slice::from_raw_parts(buffer, count - first_len),
+            )
+        };
+        Some(ReadBatch { first, second })
+    }
+
+    /// Publishes the read position, making it visible to the producer.
+    /// All previously read items MUST be processed before this is called.
+    ///
+    /// The Release store orders every earlier read of those items before the
+    /// producer can observe the freed positions and overwrite them.
+    ///
+    /// With a [`Self::set_finalize_interval`] above 1, the store is skipped
+    /// until that many reads are pending; skipped positions are published by
+    /// a later finalize, by [`Self::flush`], or when the consumer is dropped.
+    pub fn finalize(&mut self) {
+        self.finalize_requested = self.queue.cached_read;
+        if self.queue.cached_read.wrapping_sub(self.finalized) >= self.finalize_interval.get() {
+            self.publish_read(self.queue.cached_read);
+        }
+    }
+
+    /// Publishes the read position immediately, regardless of
+    /// [`Self::set_finalize_interval`]. All previously read items MUST be
+    /// processed before this is called.
+    pub fn flush(&mut self) {
+        self.finalize_requested = self.queue.cached_read;
+        if self.queue.cached_read != self.finalized {
+            self.publish_read(self.queue.cached_read);
+        }
+    }
+
+    /// Sets how many reads [`Self::finalize`] lets accumulate before it
+    /// publishes the read position. Defaults to 1, publishing on every call.
+    ///
+    /// Each publication is a Release store to a cache line the producer
+    /// polls, so batching them helps consumers that finalize per item. The
+    /// tradeoff is that the producer sees up to `interval - 1` fewer free
+    /// positions than are truly available, and may report the queue full or
+    /// block while it is not; an interval at or above the capacity can stall
+    /// it until [`Self::flush`].
+    pub fn set_finalize_interval(&mut self, interval: NonZeroUsize) {
+        self.finalize_interval = interval;
+    }
+
+    fn publish_read(&mut self, position: usize) {
+        self.finalized = position;
+        let header = self.queue.header();
+        // Release publication; `wake` supplies the fence that pairs it with
+        // a registering waiter and must be called unconditionally; see the
+        // `futex` module docs.
+        header.read.store(position, Ordering::Release);
+        header.space_waiters.wake(&header.read, 1);
+    }
+
+    /// Publishes only the first `count` positions read since the last
+    /// finalize, and rewinds the cached read position so the rest are read
+    /// again.
+    ///
+    /// `count` is clamped to the number of unfinalized reads. All of the first
+    /// `count` read items MUST be processed before this is called. Positions
+    /// already passed to [`Self::finalize`] are kept and published along with
+    /// the first `count`, even if [`Self::set_finalize_interval`] deferred
+    /// them.
+    pub fn finalize_upto(&mut self, count: usize) {
+        let finalized = self.finalize_requested;
+        let count = count.min(self.queue.cached_read.wrapping_sub(finalized));
+        self.queue.cached_read = finalized.wrapping_add(count);
+        self.flush();
     }
 
     /// Synchronizes the consumer's cached write position with the queue's write position.
+    ///
+    /// This is an Acquire load paired with the Release store in
+    /// [`Producer::commit`], so every item up to the synced position is fully
+    /// written before it is read. Reading several items before a single
+    /// [`Self::finalize`] is therefore as safe as reading one; no further
+    /// fence is needed.
     pub fn sync(&mut self) {
         self.queue.load_write();
     }
 
+    /// Returns whether the producer has called [`Producer::close`].
+    pub fn is_closed(&self) -> bool {
+        self.queue.header().closed.load(Ordering::Acquire)
+    }
+
+    /// Returns whether the process that last attached a [`Producer`] still
+    /// exists.
+    ///
+    /// This is advisory: a process ID can be reused after the producer exits,
+    /// and a producer in another PID namespace is not visible. It returns
+    /// `false` if no producer has attached.
+    #[cfg(unix)]
+    pub fn producer_alive(&self) -> bool {
+        let pid = self.queue.header().producer_pid.load(Ordering::Relaxed);
+        let Ok(pid) = libc::pid_t::try_from(pid) else {
+            return false;
+        };
+        if pid == 0 {
+            return false;
+        }
+        // SAFETY: signal 0 only checks that `pid` exists and can be signaled.
+        if unsafe { libc::kill(pid, 0) } == 0 {
+            return true;
+        }
+        // The process exists but belongs to another user.
+        std::io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+    }
+
+    /// Records that the consumer is alive, for [`Producer::consumer_heartbeat_within`].
+    ///
+    /// This reads the clock, so call it periodically rather than on every
+    /// finalize.
+    #[cfg(unix)]
+    pub fn heartbeat(&self) {
+        self.queue
+            .header()
+            .consumer_heartbeat
+            .store(monotonic_nanos(), Ordering::Relaxed);
+    }
+
+    /// Returns whether the producer called [`Producer::heartbeat`] within the
+    /// last `timeout`.
+    ///
+    /// This is advisory and time-based, like [`Producer::consumer_heartbeat_within`].
+    #[cfg(unix)]
+    pub fn producer_heartbeat_within(&self, timeout: Duration) -> bool {
+        heartbeat_within(&self.queue.header().producer_heartbeat, timeout)
+    }
+
+    /// Returns whether the queue was initialized again since this consumer
+    /// attached, e.g. by a restarted producer re-creating the file in place.
+    ///
+    /// Once this returns `true` the consumer's positions are meaningless and
+    /// it should join again. A file truncated to zero before being re-created
+    /// loses the count and is not detected.
+    pub fn generation_changed(&self) -> bool {
+        self.queue.header().generation.load(Ordering::Acquire) != self.generation
+    }
+
+    /// Returns whether the queue is closed and every item committed before
+    /// the close has been read, so no more items will ever arrive.
+    ///
+    /// This synchronizes the cached write position.
+    pub fn is_finished(&mut self) -> bool {
+        // Load the flag first: its Acquire makes every commit made before
+        // the close visible to the sync below.
+        let closed = self.is_closed();
+        self.sync();
+        closed && self.queue.is_empty()
+    }
+
+    /// Discards every committed item and finalizes, leaving the queue empty.
+    ///
+    /// Only the consumer may clear, since the read position is its own to
+    /// publish; items the producer commits concurrently may or may not be
+    /// discarded. Discarded values are not dropped. Any reads not yet
+    /// finalized are discarded too, so no previously read item may still be
+    /// in use.
+    pub fn clear(&mut self) {
+        self.sync();
+        self.queue.cached_read = self.queue.cached_write;
+        self.flush();
+    }
+
+    /// Sets how many times blocking reads recheck the queue before parking
+    /// on the futex. Defaults to 2048.
+    ///
+    /// Raising this keeps a pinned consumer from parking too eagerly; zero
+    /// parks after a single failed check.
+    pub fn set_spin_attempts(&mut self, spin_attempts: usize) {
+        self.spin_attempts = spin_attempts;
+    }
+
+    /// Sets the `eventfd` the producer signals after every commit; see
+    /// [`Producer::set_eventfd`].
+    ///
+    /// The descriptor should be created with `EFD_NONBLOCK` so
+    /// [`Self::clear_readiness`] never blocks. The consumer does not own it:
+    /// the caller must keep it open while it is set, and close it afterwards.
+    #[cfg(target_os = "linux")]
+    pub fn set_readiness_fd(&mut self, readiness_fd: Option<RawFd>) {
+        self.readiness_fd = readiness_fd;
+    }
+
+    /// Returns the `eventfd` set by [`Self::set_readiness_fd`], for
+    /// registering with epoll.
+    #[cfg(target_os = "linux")]
+    pub fn readiness_fd(&self) -> Option<RawFd> {
+        self.readiness_fd
+    }
+
+    /// Resets the readiness `eventfd` counter, returning whether it had been
+    /// signaled.
+    ///
+    /// Call this before [`Self::sync`] and draining the queue, not after, so a
+    /// commit that races with the drain leaves the descriptor readable.
+    #[cfg(target_os = "linux")]
+    pub fn clear_readiness(&self) -> bool {
+        let Some(readiness_fd) = self.readiness_fd else {
+            return false;
+        };
+        let mut counter = 0u64;
+        // SAFETY: `counter` is a valid 8-byte buffer, the size an eventfd
+        //         read requires.
+        let result = unsafe {
+            libc::read(
+                readiness_fd,
+                (&mut counter as *mut u64).cast(),
+                core::mem::size_of::<u64>(),
+            )
+        };
+        result == core::mem::size_of::<u64>() as isize
+    }
+
     /// Blocks until at least one committed item is readable or `timeout` elapses.
+    ///
+    /// The thread parks on the queue's futex once a short spin finds no data;
+    /// [`Producer::commit`] wakes it. Pass [`Duration::MAX`] to wait without a
+    /// deadline.
     pub fn wait_readable_timeout(&mut self, timeout: Duration) -> Result<(), WaitError> {
         let header = self.queue.header;
         // SAFETY: `header` points to this consumer's live shared queue header.
         let header = unsafe { header.as_ref() };
         header
             .waiters
-            .wait_for(&header.write, SPIN_ATTEMPTS, timeout, || {
+            .wait_for(&header.write, self.spin_attempts, timeout, || {
                 self.queue.load_write();
                 if !self.queue.is_empty() {
                     Some(())
@@ -359,15 +1459,202 @@ impl<T> Consumer<T> {
         let header = unsafe { header.as_ref() };
         header
             .waiters
-            .wait_for(&header.write, SPIN_ATTEMPTS, timeout, || {
+            .wait_for(&header.write, self.spin_attempts, timeout, || {
                 self.queue.load_write();
                 self.try_read_ptr()
             })
     }
 }
 
+impl<T: Copy> Consumer<T> {
+    /// Copies out the next value and finalizes the read position, or returns
+    /// `None` if the queue is empty.
+    ///
+    /// The cached write position is synchronized first if the queue appears
+    /// empty.
+    pub fn pop(&mut self) -> Option<T> {
+        if self.is_empty() {
+            self.sync();
+        }
+        let value = self.try_read().copied()?;
+        self.finalize();
+        Some(value)
+    }
+
+    /// Appends up to `max` values to `out`, in order, and finalizes the read
+    /// position once. Returns the number appended.
+    ///
+    /// The cached write position is synchronized first if fewer than `max`
+    /// values appear available. `out` grows by at most the number of values
+    /// available, not by `max`.
+    pub fn read_into(&mut self, out: &mut Vec<T>, max: usize) -> usize {
+        if self.len() < max {
+            self.sync();
+        }
+        let count = max.min(self.len());
+        out.reserve(count);
+
+        let copied = self.pop_slice(&mut out.spare_capacity_mut()[..count]);
+        // SAFETY: `pop_slice` initialized the first `copied` spare elements.
+        unsafe { out.set_len(out.len() + copied) };
+        copied
+    }
+
+    /// Copies up to `out.len()` values into the front of `out`, in order, and
+    /// finalizes the read position once. Returns the number copied; exactly
+    /// that many leading elements of `out` are initialized.
+    ///
+    /// The cached write position is synchronized first if fewer than
+    /// `out.len()` values appear available.
+    pub fn pop_slice(&mut self, out: &mut [MaybeUninit<T>]) -> usize {
+        if self.len() < out.len() {
+            self.sync();
+        }
+
+        let mut copied = 0;
+        // At most two runs: up to the end of the ring, then from its start.
+        while copied < out.len() {
+            let run = self.read_slice(out.len() - copied);
+            if run.is_empty() {
+                break;
+            }
+            let len = run.len();
+            // SAFETY: `out[copied..]` has room for `len` values,
+            //         `MaybeUninit<T>` has the layout of `T`, and the caller's
+            //         slice cannot overlap the queue's buffer.
+            unsafe {
+                core::ptr::copy_nonoverlapping(
+                    run.as_ptr(),
+                    out[copied..].as_mut_ptr().cast::<T>(),
+                    len,
+                )
+            };
+            copied += len;
+        }
+
+        if copied > 0 {
+            self.finalize();
+        }
+        copied
+    }
+}
+
 unsafe impl<T: Send> Send for Consumer<T> {}
 
+impl<T> Drop for Consumer<T> {
+    /// Publishes any positions [`Consumer::finalize`] deferred under
+    /// [`Consumer::set_finalize_interval`]. Reads that were never finalized
+    /// stay unpublished.
+    fn drop(&mut self) {
+        if self.finalize_requested != self.finalized {
+            self.publish_read(self.finalize_requested);
+        }
+    }
+}
+
+#[must_use]
+/// A reservation for one committed consumer slot.
+///
+/// Dropping the guard finalizes the read position.
+pub struct ReadGuard<'a, T> {
+    consumer: &'a mut Consumer<T>,
+    cell: NonNull<T>,
+}
+
+impl<T> AsRef<T> for ReadGuard<'_, T> {
+    /// Returns a shared reference to the reserved slot.
+    fn as_ref(&self) -> &T {
+        // SAFETY: The cell was reserved for reading.
+        //         IF producer properly wrote items, or T is POD, it is
+        //         safe to convert to reference here.
+        unsafe { self.cell.as_ref() }
+    }
+}
+
+impl<T> Drop for ReadGuard<'_, T> {
+    fn drop(&mut self) {
+        self.consumer.finalize();
+    }
+}
+
+#[must_use]
+/// An iterator over the values read by [`Consumer::drain`].
+///
+/// Dropping the iterator finalizes the read position.
+pub struct Drain<'a, T> {
+    consumer: &'a mut Consumer<T>,
+}
+
+impl<T> Iterator for Drain<'_, T> {
+    type Item = NonNull<T>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.consumer.try_read_ptr()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = self.consumer.len();
+        (remaining, Some(remaining))
+    }
+}
+
+impl<T> ExactSizeIterator for Drain<'_, T> {}
+impl<T> FusedIterator for Drain<'_, T> {}
+
+impl<T> Drop for Drain<'_, T> {
+    fn drop(&mut self) {
+        self.consumer.finalize();
+    }
+}
+
+/// Consecutive values read by [`Consumer::try_read_batch`].
+///
+/// The values may wrap around the end of the ring, so they are exposed as up
+/// to two slices. The batch does not release its slots; call
+/// [`Consumer::finalize`] once the values are processed.
+pub struct ReadBatch<'a, T> {
+    first: &'a [T],
+    second: &'a [T],
+}
+
+impl<'a, T> ReadBatch<'a, T> {
+    pub fn len(&self) -> usize {
+        self.first.len() + self.second.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        // A batch is only returned with at least one value.
+        false
+    }
+
+    /// Returns the values in order as two slices; the second is empty unless
+    /// the batch wraps around the end of the ring.
+    pub fn as_slices(&self) -> (&'a [T], &'a [T]) {
+        (self.first, self.second)
+    }
+
+    /// Returns the value at `index`, or `None` if it is outside the batch.
+    pub fn get(&self, index: usize) -> Option<&'a T> {
+        match index.checked_sub(self.first.len()) {
+            None => self.first.get(index),
+            Some(index) => self.second.get(index),
+        }
+    }
+
+    /// Iterates over the values in order.
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = &'a T> + FusedIterator + 'a {
+        self.first.iter().chain(self.second.iter())
+    }
+}
+
+impl<T> Index<usize> for ReadBatch<'_, T> {
+    type Output = T;
+
+    fn index(&self, index: usize) -> &Self::Output {
+        self.get(index).expect("read batch index out of bounds")
+    }
+}
+
 struct SharedQueue<T> {
     header: NonNull<SharedQueueHeader>,
     buffer: NonNull<T>,
@@ -456,6 +1743,57 @@ impl<T> SharedQueue<T> {
         index & self.buffer_mask
     }
 
+    /// Counts a reservation that found the queue full, if the `stats`
+    /// feature is enabled.
+    #[inline]
+    fn record_full(&self) {
+        #[cfg(feature = "stats")]
+        {
+            // Only the producer writes this counter, so a plain increment
+            // avoids an RMW.
+            let full_events = &self.header().full_events;
+            full_events.store(
+                full_events.load(Ordering::Relaxed).wrapping_add(1),
+                Ordering::Relaxed,
+            );
+        }
+    }
+
+    /// Raises the high-water mark to the fill at the just-published write
+    /// `position`, if the `stats` feature is enabled.
+    #[inline]
+    #[cfg_attr(not(feature = "stats"), allow(unused_variables))]
+    fn record_fill(&self, position: usize) {
+        #[cfg(feature = "stats")]
+        {
+            let header = self.header();
+            // Measure the published positions on both sides: reservations past
+            // `position` are not committed, and the producer's cached read
+            // position may be stale, either of which would inflate the mark.
+            let fill = position.wrapping_sub(header.read.load(Ordering::Relaxed)) as u64;
+            // Only the producer writes the mark, so no CAS is needed.
+            if fill > header.high_water_mark.load(Ordering::Relaxed) {
+                header.high_water_mark.store(fill, Ordering::Relaxed);
+            }
+        }
+    }
+
+    fn metadata(&self) -> &[u8] {
+        let header = self.header();
+        // A corrupted length must not index out of bounds.
+        &header.metadata[..header.metadata_len.min(METADATA_CAPACITY)]
+    }
+
+    fn stats(&self) -> QueueStats {
+        let header = self.header();
+        QueueStats {
+            committed: header.write.load(Ordering::Relaxed) as u64,
+            finalized: header.read.load(Ordering::Relaxed) as u64,
+            full: header.full_events.load(Ordering::Relaxed),
+            high_water_mark: header.high_water_mark.load(Ordering::Relaxed),
+        }
+    }
+
     #[inline]
     fn header(&self) -> &SharedQueueHeader {
         // SAFETY: See safety on `from_header`. `header` is non-null and aligned.
@@ -473,6 +1811,23 @@ impl<T> SharedQueue<T> {
     }
 }
 
+/// Snapshot of a queue's lifetime counters.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct QueueStats {
+    /// Items committed by the producer.
+    pub committed: u64,
+    /// Items finalized by the consumer.
+    pub finalized: u64,
+    /// Writes that found the queue full: each failed reservation or push,
+    /// and each timed one that had to wait, counts once. Counted only by
+    /// producers built with the `stats` feature; zero otherwise.
+    pub full: u64,
+    /// Most items ever committed but not yet finalized, measured at each
+    /// commit. Tracked only by producers built with the `stats` feature;
+    /// zero otherwise.
+    pub high_water_mark: u64,
+}
+
 /// Header in shared memory for the queue.
 #[repr(C)]
 struct SharedQueueHeader {
@@ -480,12 +1835,34 @@ struct SharedQueueHeader {
     magic: AtomicU64,
     version: u32,
     buffer_mask: u32,
+    element_size: usize,
+    element_align: usize,
+    /// Set by [`Producer::close`]; never cleared.
+    closed: AtomicBool,
+    /// Process ID of the most recent producer, or 0 before one attaches.
+    producer_pid: AtomicU32,
+    /// Number of times this header has been initialized.
+    generation: AtomicU64,
+    /// Reservations that found the queue full; see [`QueueStats::full`].
+    full_events: AtomicU64,
+    /// Peak committed fill; see [`QueueStats::high_water_mark`].
+    high_water_mark: AtomicU64,
+    /// Monotonic clock reading in nanoseconds at each side's last
+    /// heartbeat, or 0 if it has never beaten.
+    producer_heartbeat: AtomicU64,
+    consumer_heartbeat: AtomicU64,
+    /// Application metadata written once at creation; see
+    /// [`Producer::create_with_metadata`].
+    metadata_len: usize,
+    metadata: [u8; METADATA_CAPACITY],
 
     // Hot cache lines.
     write: CacheAlignedAtomicSize,
     read: CacheAlignedAtomicSize,
     /// Consumer wait/wake coordination.
     waiters: Waiters,
+    /// Producer wait/wake coordination.
+    space_waiters: Waiters,
 }
 
 impl SharedQueueHeader {
@@ -496,12 +1873,19 @@ impl SharedQueueHeader {
     ///   queue header.
     /// - The returned `region` must not be passed to any other queue-header
     ///   initialization routine.
-    unsafe fn create<T>(file: &File, size: usize) -> Result<(Arc<Region>, NonNull<Self>), Error> {
-        file.set_len(size as u64)?;
+    unsafe fn create<T>(
+        file: &File,
+        size: usize,
+        metadata: &[u8],
+    ) -> Result<(Arc<Region>, NonNull<Self>), Error> {
+        // Reject bad metadata and sizes before touching the file.
+        Self::check_metadata_len(metadata)?;
+        validate_region_size(size, minimum_file_size::<T>(1))?;
+        size_file(file, size)?;
 
         let region = Region::map_file(file, size)?;
         // SAFETY: caller guarantees this mapping is initialized exactly once.
-        let header = unsafe { Self::create_in_region::<T>(&region) }?;
+        let header = unsafe { Self::create_in_region::<T>(&region, metadata) }?;
         Ok((region, header))
     }
 
@@ -509,7 +1893,11 @@ impl SharedQueueHeader {
     ///
     /// # Safety
     /// - This function must be called at most once for a given `region`.
-    unsafe fn create_in_region<T>(region: &Arc<Region>) -> Result<NonNull<Self>, Error> {
+    unsafe fn create_in_region<T>(
+        region: &Arc<Region>,
+        metadata: &[u8],
+    ) -> Result<NonNull<Self>, Error> {
+        Self::check_metadata_len(metadata)?;
         let buffer_size_in_items = Self::calculate_buffer_size_in_items::<T>(region.size())?;
         let header = region.addr().cast();
         // SAFETY: The header is non-null and aligned properly.
@@ -518,10 +1906,21 @@ impl SharedQueueHeader {
         //         alignment of `SharedQueueHeader`.
         //         Access is exclusive because the caller guarantees this region
         //         is initialized at most once.
-        unsafe { Self::initialize(header, buffer_size_in_items) };
+        unsafe { Self::initialize::<T>(header, buffer_size_in_items, metadata) };
         Ok(header)
     }
 
+    fn check_metadata_len(metadata: &[u8]) -> Result<(), Error> {
+        if metadata.len() > METADATA_CAPACITY {
+            return Err(Error::MetadataTooLarge {
+                len: metadata.len(),
+                maximum: METADATA_CAPACITY,
+            });
+        }
+
+        Ok(())
+    }
+
     const fn buffer_offset<T>() -> usize {
         const {
             assert!(
@@ -544,18 +1943,24 @@ impl SharedQueueHeader {
 
         let buffer_offset = Self::buffer_offset::<T>();
         if file_size < buffer_offset {
-            return Err(Error::InvalidBufferSize);
+            return Err(Error::FileTooSmall {
+                given: file_size,
+                minimum: minimum_file_size::<T>(1),
+            });
         }
 
         // The buffer size (in units of T) must be a power of two.
         let buffer_size_in_bytes = file_size - buffer_offset;
         let mut buffer_size_in_items = buffer_size_in_bytes / core::mem::size_of::<T>();
+        if buffer_size_in_items == 0 {
+            return Err(Error::ItemTooLarge {
+                item_size: core::mem::size_of::<T>(),
+                available: buffer_size_in_bytes,
+            });
+        }
         if !buffer_size_in_items.is_power_of_two() {
             // If not a power of two, round down to the previous power of two.
             buffer_size_in_items = buffer_size_in_items.next_power_of_two() >> 1;
-            if buffer_size_in_items == 0 {
-                return Err(Error::InvalidBufferSize);
-            }
         }
 
         // The buffer mask is stored as u32, so the capacity must fit.
@@ -572,7 +1977,11 @@ impl SharedQueueHeader {
     /// - `header` must be non-null and properly aligned.
     /// - `header` allocation must be large enough to hold the header and the buffer.
     /// - `access` to `header` must be unique when this is called.
-    unsafe fn initialize(mut header: NonNull<Self>, buffer_size_in_items: usize) {
+    unsafe fn initialize<T>(
+        mut header: NonNull<Self>,
+        buffer_size_in_items: usize,
+        metadata: &[u8],
+    ) {
         // SAFETY:
         // - `header` is non-null and aligned properly.
         // - `access` to `header` is unique.
@@ -580,19 +1989,36 @@ impl SharedQueueHeader {
         header.write.store(0, Ordering::Release);
         header.read.store(0, Ordering::Release);
         header.waiters.initialize();
+        header.space_waiters.initialize();
         header.buffer_mask = u32::try_from(buffer_size_in_items - 1).unwrap();
+        header.element_size = core::mem::size_of::<T>();
+        header.element_align = core::mem::align_of::<T>();
+        header.closed.store(false, Ordering::Release);
+        header.producer_pid.store(0, Ordering::Relaxed);
+        header.full_events.store(0, Ordering::Relaxed);
+        header.high_water_mark.store(0, Ordering::Relaxed);
+        header.producer_heartbeat.store(0, Ordering::Relaxed);
+        header.consumer_heartbeat.store(0, Ordering::Relaxed);
+        header.metadata_len = metadata.len();
+        header.metadata = [0; METADATA_CAPACITY];
+        header.metadata[..metadata.len()].copy_from_slice(metadata);
         header.version = VERSION;
+        // A re-created queue keeps the previous count, so attached consumers
+        // can tell their queue was reset under them.
+        header.generation.fetch_add(1, Ordering::Release);
         header.magic.store(MAGIC, Ordering::Release);
     }
 
     fn join<T>(file: &File) -> Result<(Arc<Region>, NonNull<Self>), Error> {
         let file_size = file.metadata()?.len() as usize;
+        validate_region_size(file_size, minimum_file_size::<T>(1))?;
         let region = Region::map_file(file, file_size)?;
         let header = Self::join_region::<T>(&region)?;
         Ok((region, header))
     }
 
     fn join_region<T>(region: &Arc<Region>) -> Result<NonNull<Self>, Error> {
+        validate_region_size(region.size(), minimum_file_size::<T>(1))?;
         let header = region.addr().cast::<Self>();
         {
             // SAFETY: The header is non-null and aligned properly.
@@ -609,9 +2035,15 @@ impl SharedQueueHeader {
                     actual: header.version,
                 });
             }
-            if (header.buffer_mask as usize).wrapping_add(1)
-                != Self::calculate_buffer_size_in_items::<T>(region.size())?
-            {
+            validate_element_layout::<T>(header.element_size, header.element_align)?;
+            let buffer_size_in_items = (header.buffer_mask as usize).wrapping_add(1);
+            // A file truncated after creation must not be read past its end.
+            let minimum = buffer_size_in_items
+                .checked_mul(core::mem::size_of::<T>())
+                .and_then(|buffer_size| buffer_size.checked_add(Self::buffer_offset::<T>()))
+                .ok_or(Error::InvalidBufferSize)?;
+            validate_region_size(region.size(), minimum)?;
+            if buffer_size_in_items != Self::calculate_buffer_size_in_items::<T>(region.size())? {
                 return Err(Error::InvalidBufferSize);
             }
         }
@@ -777,30 +2209,1133 @@ mod tests {
     }
 
     #[test]
-    fn test_read_ptr_timeout_observes_commit() {
+    fn test_len_reflects_cached_positions() {
         for create_queue in test_queue_creators::<u64>() {
             let (mut producer, mut consumer) = create_queue(64);
+            assert!(producer.is_empty());
+            assert!(consumer.is_empty());
 
-            let spot = unsafe { producer.reserve() }.expect("reserve failed");
-            unsafe { spot.write(42) };
+            producer.try_write(1).unwrap();
+            producer.try_write(2).unwrap();
+            producer.commit();
+            assert_eq!(producer.len(), 2);
+            assert_eq!(consumer.len(), 0); // consumer has not synced yet
 
-            assert!(matches!(
-                consumer.wait_readable_timeout(Duration::ZERO),
-                Err(WaitError::Timeout)
-            ));
+            consumer.sync();
+            assert_eq!(consumer.len(), 2);
+            assert_eq!(consumer.try_read(), Some(&1));
+            assert_eq!(consumer.len(), 1);
+            consumer.finalize();
+
+            assert_eq!(producer.len(), 2); // producer has not synced yet
+            producer.sync();
+            assert_eq!(producer.len(), 1);
+        }
+    }
+
+    #[test]
+    fn test_remaining_capacity() {
+        for create_queue in test_queue_creators::<u64>() {
+            let (mut producer, mut consumer) = create_queue(4);
+            assert_eq!(producer.remaining_capacity(), 4);
+            assert!(!producer.is_full());
 
+            for value in 0..4 {
+                producer.try_write(value).unwrap();
+            }
+            assert_eq!(producer.remaining_capacity(), 0);
+            assert!(producer.is_full());
             producer.commit();
 
-            let ptr = match consumer.read_ptr_timeout(Duration::ZERO) {
-                Ok(ptr) => ptr,
-                Err(WaitError::Timeout) => panic!("read timed out after commit"),
-            };
-            // SAFETY: `ptr` points at a readable `u64`; the value is Copy.
-            assert_eq!(unsafe { ptr.read() }, 42);
+            consumer.sync();
+            assert_eq!(consumer.try_read(), Some(&0));
+            consumer.finalize();
+
+            // Still a lower bound until the producer syncs.
+            assert_eq!(producer.remaining_capacity(), 0);
+            producer.sync();
+            assert_eq!(producer.remaining_capacity(), 1);
+            assert!(!producer.is_full());
+        }
+    }
+
+    #[test]
+    fn test_mapped_len() {
+        let (producer, consumer) = create_heap_test_queue::<u64>(64);
+        assert_eq!(producer.mapped_len(), minimum_region_size::<u64>(64));
+        assert_eq!(consumer.mapped_len(), minimum_region_size::<u64>(64));
+    }
+
+    #[cfg(not(miri))]
+    #[test]
+    fn test_mapped_len_includes_unused_tail() {
+        let file = create_temp_shmem_file().expect("failed to create temp file");
+        // Not a power-of-two fit: the capacity rounds down, the mapping does not.
+        let file_size = minimum_file_size::<u64>(64) + 100;
+        let producer =
+            unsafe { Producer::<u64>::create(&file, file_size) }.expect("failed to create");
+        let consumer = unsafe { Consumer::<u64>::join(&file) }.expect("failed to join");
+
+        assert_eq!(producer.capacity(), 64);
+        assert_eq!(producer.mapped_len(), file_size);
+        assert_eq!(consumer.mapped_len(), file_size);
+    }
+
+    #[test]
+    fn test_write_guard_commits_on_drop() {
+        for create_queue in test_queue_creators::<u64>() {
+            let (mut producer, mut consumer) = create_queue(2);
+
+            let guard = unsafe { producer.try_reserve_write() }.expect("reserve failed");
+            consumer.sync();
+            assert!(consumer.is_empty()); // not committed until the guard drops
+            guard.write(5);
+
+            let mut guard = unsafe { producer.try_reserve_write() }.expect("reserve failed");
+            guard.as_mut().write(6);
+            drop(guard);
+            assert!(unsafe { producer.try_reserve_write() }.is_none());
+
+            consumer.sync();
+            assert_eq!(consumer.try_read(), Some(&5));
+            assert_eq!(consumer.try_read(), Some(&6));
+            consumer.finalize();
+        }
+    }
+
+    #[test]
+    fn test_write_batch_wraps_and_commits_on_drop() {
+        for create_queue in test_queue_creators::<u64>() {
+            let (mut producer, mut consumer) = create_queue(4);
+
+            producer.try_write(0).unwrap();
+            producer.try_write(1).unwrap();
+            producer.commit();
+            consumer.sync();
+            assert_eq!(consumer.try_read(), Some(&0));
+            assert_eq!(consumer.try_read(), Some(&1));
+            consumer.finalize();
+            producer.sync();
+
+            let count = NonZeroUsize::new(5).unwrap();
+            assert!(unsafe { producer.try_reserve_write_batch(count) }.is_none());
+
+            // The batch wraps past the end of the ring.
+            let count = NonZeroUsize::new(4).unwrap();
+            let mut batch =
+                unsafe { producer.try_reserve_write_batch(count) }.expect("reserve failed");
+            assert_eq!(batch.len(), 4);
+            for index in 0..batch.len() {
+                unsafe { batch.write(index, 10 + index as u64) };
+            }
+            consumer.sync();
+            assert!(consumer.is_empty()); // not committed until the batch drops
+            drop(batch);
+            assert!(unsafe { producer.reserve() }.is_none());
+
+            consumer.sync();
+            for expected in 10..14 {
+                assert_eq!(consumer.try_read(), Some(&expected));
+            }
             consumer.finalize();
         }
     }
 
+    #[test]
+    fn test_item_larger_than_buffer_is_reported() {
+        type Item = [u8; 4096];
+        let file_size = SharedQueueHeader::buffer_offset::<Item>() + 128;
+        assert!(matches!(
+            SharedQueueHeader::calculate_buffer_size_in_items::<Item>(file_size),
+            Err(Error::ItemTooLarge {
+                item_size: 4096,
+                available: 128,
+            })
+        ));
+    }
+
+    #[cfg(not(miri))]
+    #[test]
+    fn test_file_too_small_is_reported() {
+        let minimum = minimum_file_size::<u64>(1);
+        let file = create_temp_shmem_file().expect("failed to create temp file");
+        assert!(matches!(
+            unsafe { Consumer::<u64>::join(&file) },
+            Err(Error::FileTooSmall { given: 0, minimum: m }) if m == minimum
+        ));
+
+        let err = unsafe { Producer::<u64>::create(&file, 16) };
+        assert!(matches!(err, Err(Error::FileTooSmall { given: 16, .. })));
+    }
+
+    #[cfg(not(miri))]
+    #[test]
+    fn test_create_rejects_size_without_a_slot_before_sizing() {
+        type Item = [u8; 4096];
+        let size = buffer_offset::<Item>() + 128;
+        let file = create_temp_shmem_file().expect("failed to create temp file");
+        assert!(matches!(
+            unsafe { Producer::<Item>::create(&file, size) },
+            Err(Error::FileTooSmall { given, minimum })
+                if given == size && minimum == minimum_file_size::<Item>(1)
+        ));
+        assert_eq!(file.metadata().unwrap().len(), 0);
+    }
+
+    #[cfg(not(miri))]
+    #[test]
+    fn test_truncated_file_is_reported() {
+        let size = minimum_file_size::<u64>(64);
+        let truncated = minimum_file_size::<u64>(32);
+        let file = create_temp_shmem_file().expect("failed to create temp file");
+        drop(unsafe { Producer::<u64>::create(&file, size) }.expect("failed to create queue"));
+
+        file.set_len(truncated as u64).expect("failed to truncate");
+        assert!(matches!(
+            unsafe { Consumer::<u64>::join(&file) },
+            Err(Error::FileTooSmall { given, minimum })
+                if given == truncated && minimum == size
+        ));
+    }
+
+    #[test]
+    fn test_join_rejects_element_layout_mismatch() {
+        let (producer, _consumer) = pair::<u64>(64).expect("failed to create queue");
+        let region = &producer.queue.region;
+
+        // Same size as `u64`, but different alignment.
+        let err = SharedQueueHeader::join_region::<[u8; 8]>(region);
+        assert!(matches!(err, Err(Error::ElementLayoutMismatch { .. })));
+
+        // Different size.
+        let err = SharedQueueHeader::join_region::<u32>(region);
+        assert!(matches!(
+            err,
+            Err(Error::ElementLayoutMismatch { expected, found })
+                if expected.size() == 4 && found.size() == 8
+        ));
+    }
+
+    #[test]
+    fn test_read_batch_wraps_and_releases_on_finalize() {
+        for create_queue in test_queue_creators::<u64>() {
+            let (mut producer, mut consumer) = create_queue(4);
+            let max = NonZeroUsize::new(4).unwrap();
+            assert!(consumer.try_read_batch(max).is_none());
+
+            for value in 0..3 {
+                producer.try_write(value).unwrap();
+            }
+            producer.commit();
+            consumer.sync();
+            let batch = consumer
+                .try_read_batch(NonZeroUsize::new(2).unwrap())
+                .expect("read batch");
+            assert_eq!(batch.as_slices(), (&[0, 1][..], &[][..]));
+            consumer.finalize();
+            producer.sync();
+
+            for value in 3..6 {
+                producer.try_write(value).unwrap();
+            }
+            producer.commit();
+            consumer.sync();
+            let batch = consumer.try_read_batch(max).expect("wrapped read batch");
+            assert_eq!(batch.len(), 4);
+            assert_eq!(batch.as_slices(), (&[2, 3][..], &[4, 5][..]));
+            assert_eq!(batch.get(2), Some(&4));
+            assert_eq!(batch[3], 5);
+            assert_eq!(batch.get(4), None);
+            assert_eq!(batch.iter().copied().collect::<Vec<_>>(), vec![2, 3, 4, 5]);
+            assert!(consumer.try_read_batch(max).is_none());
+
+            // Slots are released only once the batch is finalized.
+            producer.sync();
+            assert_eq!(producer.remaining_capacity(), 0);
+            consumer.finalize();
+            producer.sync();
+            assert_eq!(producer.remaining_capacity(), 4);
+        }
+    }
+
+    #[test]
+    fn test_raw_buffer_queue() {
+        let size = minimum_file_size::<u64>(8);
+        let layout = std::alloc::Layout::from_size_align(size + 8, 4096).unwrap();
+        let addr = NonNull::new(unsafe { std::alloc::alloc_zeroed(layout) }).unwrap();
+
+        {
+            let mut producer = unsafe { Producer::<u64>::create_raw(addr, size) }.unwrap();
+            let mut consumer = unsafe { Consumer::<u64>::join_raw(addr, size) }.unwrap();
+            assert_eq!(producer.capacity(), 8);
+            producer.push(7).unwrap();
+            consumer.sync();
+            assert_eq!(consumer.pop(), Some(7));
+
+            let misaligned = unsafe { addr.add(8) };
+            assert!(matches!(
+                unsafe { Consumer::<u64>::join_raw(misaligned, size) },
+                Err(Error::InvalidRegionAlignment { .. })
+            ));
+        }
+
+        unsafe { std::alloc::dealloc(addr.as_ptr(), layout) };
+    }
+
+    #[test]
+    fn test_close_is_observed_after_drain() {
+        for create_queue in test_queue_creators::<u64>() {
+            let (mut producer, mut consumer) = create_queue(4);
+            assert!(!consumer.is_closed());
+            assert!(!consumer.is_finished());
+
+            producer.push(1).unwrap();
+            producer.push(2).unwrap();
+            producer.close();
+            assert!(consumer.is_closed());
+
+            let mut items = Vec::new();
+            while !consumer.is_finished() {
+                items.extend(consumer.pop());
+            }
+            assert_eq!(items, [1, 2]);
+        }
+    }
+
+    #[cfg(all(unix, not(miri)))]
+    #[test]
+    fn test_producer_alive() {
+        for create_queue in test_queue_creators::<u64>() {
+            let (producer, consumer) = create_queue(4);
+            assert!(consumer.producer_alive());
+
+            // A process ID that cannot exist.
+            producer
+                .queue
+                .header()
+                .producer_pid
+                .store(i32::MAX as u32, Ordering::Relaxed);
+            assert!(!consumer.producer_alive());
+        }
+    }
+
+    #[cfg(not(miri))]
+    #[test]
+    fn test_generation_changes_when_recreated() {
+        let file = create_temp_shmem_file().expect("failed to create temp file");
+        let file_size = minimum_file_size::<u64>(4);
+        let producer = unsafe { Producer::<u64>::create(&file, file_size) }.unwrap();
+        let consumer = unsafe { Consumer::<u64>::join(&file) }.unwrap();
+        assert!(!consumer.generation_changed());
+
+        // A restarted producer re-creates the queue in the same file.
+        drop(producer);
+        let _producer = unsafe { Producer::<u64>::create(&file, file_size) }.unwrap();
+        assert!(consumer.generation_changed());
+
+        let rejoined = unsafe { Consumer::<u64>::join(&file) }.unwrap();
+        assert!(!rejoined.generation_changed());
+    }
+
+    #[cfg(all(unix, not(miri)))]
+    #[test]
+    fn test_heartbeats() {
+        for create_queue in test_queue_creators::<u64>() {
+            let (producer, consumer) = create_queue(4);
+            assert!(!producer.consumer_heartbeat_within(Duration::MAX));
+            assert!(!consumer.producer_heartbeat_within(Duration::MAX));
+
+            producer.heartbeat();
+            consumer.heartbeat();
+            assert!(producer.consumer_heartbeat_within(Duration::from_secs(60)));
+            assert!(consumer.producer_heartbeat_within(Duration::from_secs(60)));
+
+            std::thread::sleep(Duration::from_millis(2));
+            assert!(!producer.consumer_heartbeat_within(Duration::from_millis(1)));
+        }
+    }
+
+    #[test]
+    fn test_push_slice_wraps_and_stops_when_full() {
+        for create_queue in test_queue_creators::<u64>() {
+            let (mut producer, mut consumer) = create_queue(4);
+            assert_eq!(producer.push_slice(&[]), 0);
+
+            // Advance by three so the next run crosses the end of the ring.
+            assert_eq!(producer.push_slice(&[0, 0, 0]), 3);
+            consumer.sync();
+            while consumer.pop().is_some() {}
+
+            assert_eq!(producer.push_slice(&[1, 2, 3, 4, 5]), 4);
+            assert_eq!(producer.push_slice(&[6]), 0);
+
+            consumer.sync();
+            let mut items = Vec::new();
+            while let Some(item) = consumer.pop() {
+                items.push(item);
+            }
+            assert_eq!(items, [1, 2, 3, 4]);
+        }
+    }
+
+    #[test]
+    fn test_pop_slice_wraps() {
+        for create_queue in test_queue_creators::<u64>() {
+            let (mut producer, mut consumer) = create_queue(4);
+            let mut out = [MaybeUninit::<u64>::uninit(); 8];
+            assert_eq!(consumer.pop_slice(&mut out), 0);
+
+            // Advance by three so the next run crosses the end of the ring.
+            assert_eq!(producer.push_slice(&[0, 0, 0]), 3);
+            assert_eq!(consumer.pop_slice(&mut out[..3]), 3);
+
+            assert_eq!(producer.push_slice(&[1, 2, 3, 4]), 4);
+            assert_eq!(consumer.pop_slice(&mut out), 4);
+            let copied: Vec<u64> = out[..4]
+                .iter()
+                .map(|item| unsafe { item.assume_init() })
+                .collect();
+            assert_eq!(copied, [1, 2, 3, 4]);
+
+            // The whole batch was finalized.
+            producer.sync();
+            assert!(producer.is_empty());
+        }
+    }
+
+    #[test]
+    fn test_read_into_appends_across_wrap() {
+        for create_queue in test_queue_creators::<u64>() {
+            let (mut producer, mut consumer) = create_queue(4);
+            let mut out = vec![0];
+            assert_eq!(consumer.read_into(&mut out, usize::MAX), 0);
+
+            // Advance by three so the next run crosses the end of the ring.
+            assert_eq!(producer.push_slice(&[0, 0, 0]), 3);
+            assert_eq!(consumer.read_into(&mut out, 3), 3);
+            out.clear();
+
+            assert_eq!(producer.push_slice(&[1, 2, 3, 4]), 4);
+            assert_eq!(consumer.read_into(&mut out, 3), 3);
+            assert_eq!(consumer.read_into(&mut out, usize::MAX), 1);
+            assert_eq!(out, [1, 2, 3, 4]);
+
+            producer.sync();
+            assert!(producer.is_empty());
+        }
+    }
+
+    #[test]
+    fn test_header_offsets_match_layout() {
+        let (mut producer, _consumer) = pair::<u64>(8).unwrap();
+        producer.push(5).unwrap();
+        let base = producer.queue.region.addr();
+        let u64_at = |offset: usize| unsafe { base.byte_add(offset).cast::<u64>().read() };
+        let u32_at = |offset: usize| unsafe { base.byte_add(offset).cast::<u32>().read() };
+
+        assert_eq!(u64_at(MAGIC_OFFSET), MAGIC);
+        assert_eq!(u32_at(VERSION_OFFSET), VERSION);
+        assert_eq!(u32_at(BUFFER_MASK_OFFSET), 7);
+        assert_eq!(u64_at(WRITE_OFFSET), 1);
+        assert_eq!(u64_at(READ_OFFSET), 0);
+        assert_eq!(u64_at(buffer_offset::<u64>()), 5);
+    }
+
+    #[test]
+    fn test_stats() {
+        for create_queue in test_queue_creators::<u64>() {
+            let (mut producer, mut consumer) = create_queue(2);
+            producer.push(1).unwrap();
+            producer.push(2).unwrap();
+            assert!(producer.push(3).is_err());
+            assert!(unsafe { producer.reserve_slice(1) }.is_empty());
+            consumer.sync();
+            consumer.pop().unwrap();
+
+            let counted = |n| if cfg!(feature = "stats") { n } else { 0 };
+            let expected = QueueStats {
+                committed: 2,
+                finalized: 1,
+                full: counted(2),
+                high_water_mark: counted(2),
+            };
+            assert_eq!(producer.stats(), expected);
+            assert_eq!(consumer.stats(), expected);
+
+            producer.reset_high_water_mark();
+            producer.sync();
+            producer.push(3).unwrap();
+            assert_eq!(consumer.stats().high_water_mark, counted(2));
+        }
+    }
+
+    #[cfg(not(miri))]
+    #[test]
+    fn test_timed_write_counts_full_once() {
+        for create_queue in test_queue_creators::<u64>() {
+            let (mut producer, _consumer) = create_queue(1);
+            let counted = |n| if cfg!(feature = "stats") { n } else { 0 };
+            producer.push(1).unwrap();
+
+            // Every retry while blocked finds the queue full, but the call
+            // counts once.
+            assert!(matches!(
+                unsafe { producer.reserve_timeout(Duration::from_millis(5)) },
+                Err(WaitError::Timeout)
+            ));
+            assert_eq!(producer.stats().full, counted(1));
+
+            assert_eq!(producer.push_timeout(2, Duration::from_millis(5)), Err(2));
+            assert_eq!(producer.stats().full, counted(2));
+        }
+    }
+
+    #[test]
+    fn test_high_water_mark_counts_only_published_items() {
+        for create_queue in test_queue_creators::<u64>() {
+            let (mut producer, consumer) = create_queue(8);
+            let counted = |n| if cfg!(feature = "stats") { n } else { 0 };
+            for value in 0..3 {
+                unsafe { producer.reserve().unwrap().write(value) };
+            }
+            producer.commit_upto(1);
+            assert_eq!(consumer.stats().high_water_mark, counted(1));
+
+            // A deferred commit published on drop leaves later reservations out.
+            producer.set_commit_interval(NonZeroUsize::new(4).unwrap());
+            producer.push(1).unwrap();
+            for value in 0..3 {
+                unsafe { producer.reserve().unwrap().write(value) };
+            }
+            drop(producer);
+            assert_eq!(consumer.stats().high_water_mark, counted(2));
+        }
+    }
+
+    #[cfg(not(miri))]
+    #[test]
+    fn test_metadata_is_visible_on_join() {
+        let file = create_temp_shmem_file().expect("failed to create temp file");
+        let file_size = minimum_file_size::<u64>(4);
+        let producer =
+            unsafe { Producer::<u64>::create_with_metadata(&file, file_size, b"schema=7") }
+                .unwrap();
+        let consumer = unsafe { Consumer::<u64>::join(&file) }.unwrap();
+        assert_eq!(producer.metadata(), b"schema=7");
+        assert_eq!(consumer.metadata(), b"schema=7");
+
+        let other = create_temp_shmem_file().expect("failed to create temp file");
+        let err = unsafe {
+            Producer::<u64>::create_with_metadata(&other, file_size, &[0; METADATA_CAPACITY + 1])
+        };
+        assert!(matches!(err, Err(Error::MetadataTooLarge { len: 65, .. })));
+        assert_eq!(other.metadata().unwrap().len(), 0); // untouched
+
+        let (producer, _consumer) = pair::<u64>(4).unwrap();
+        assert!(producer.metadata().is_empty());
+    }
+
+    #[test]
+    fn test_poll_syncs_and_finalizes() {
+        for create_queue in test_queue_creators::<u64>() {
+            let (mut producer, mut consumer) = create_queue(4);
+            consumer.set_finalize_interval(NonZeroUsize::new(2).unwrap());
+            assert!(consumer.poll().is_none());
+
+            assert_eq!(producer.push_slice(&[1, 2, 3]), 3);
+            let mut polled = vec![];
+            while let Some(ptr) = consumer.poll() {
+                polled.push(unsafe { ptr.read() });
+            }
+            assert_eq!(polled, [1, 2, 3]);
+
+            // Every value before the last one polled has been finalized, and
+            // the empty poll that ended the loop finalized the last one too.
+            producer.sync();
+            assert_eq!(producer.len(), 0);
+        }
+    }
+
+    #[test]
+    fn test_commit_interval_defers_publication() {
+        for create_queue in test_queue_creators::<u64>() {
+            let (mut producer, mut consumer) = create_queue(4);
+            producer.set_commit_interval(NonZeroUsize::new(3).unwrap());
+
+            producer.push(1).unwrap();
+            producer.push(2).unwrap();
+            consumer.sync();
+            assert_eq!(consumer.len(), 0);
+
+            producer.push(3).unwrap();
+            consumer.sync();
+            assert_eq!(consumer.len(), 3);
+
+            // The full check counts the unpublished item.
+            producer.push(4).unwrap();
+            assert_eq!(producer.push(5), Err(5));
+            consumer.sync();
+            assert_eq!(consumer.len(), 3);
+
+            producer.flush();
+            consumer.sync();
+            assert_eq!(consumer.len(), 4);
+        }
+    }
+
+    #[test]
+    fn test_commit_upto_keeps_deferred_commits() {
+        for create_queue in test_queue_creators::<u64>() {
+            let (mut producer, mut consumer) = create_queue(4);
+            producer.set_commit_interval(NonZeroUsize::new(4).unwrap());
+            producer.push(1).unwrap();
+            producer.push(2).unwrap();
+            unsafe { producer.reserve().unwrap().write(3) };
+
+            // Only the uncommitted reservation is rolled back.
+            producer.commit_upto(0);
+            producer.push(4).unwrap();
+            producer.flush();
+
+            consumer.sync();
+            let mut read = vec![];
+            while let Some(value) = consumer.pop() {
+                read.push(value);
+            }
+            assert_eq!(read, [1, 2, 4]);
+        }
+    }
+
+    #[test]
+    fn test_drop_publishes_deferred_commit() {
+        for create_queue in test_queue_creators::<u64>() {
+            let (mut producer, mut consumer) = create_queue(4);
+            producer.set_commit_interval(NonZeroUsize::new(4).unwrap());
+            producer.push(1).unwrap();
+            // Reserved but never committed, so it stays unpublished.
+            unsafe { producer.reserve().unwrap().write(2) };
+            drop(producer);
+
+            consumer.sync();
+            assert_eq!(consumer.len(), 1);
+        }
+    }
+
+    #[cfg(not(miri))]
+    #[test]
+    fn test_push_timeout_publishes_deferred_commits() {
+        let (mut producer, mut consumer) = pair::<u64>(4).unwrap();
+        producer.set_commit_interval(NonZeroUsize::new(16).unwrap());
+        for value in 1..=4 {
+            producer.push(value).unwrap();
+        }
+
+        let reader = std::thread::spawn(move || {
+            consumer
+                .wait_readable_timeout(Duration::from_secs(10))
+                .unwrap();
+            assert_eq!(consumer.pop(), Some(1));
+            consumer
+        });
+        // The full queue holds only deferred items, so waiting must publish
+        // them for the consumer to make room.
+        assert_eq!(producer.push_timeout(5, Duration::from_secs(10)), Ok(()));
+        producer.flush();
+
+        let mut consumer = reader.join().unwrap();
+        consumer.sync();
+        let mut read = vec![];
+        while let Some(value) = consumer.pop() {
+            read.push(value);
+        }
+        assert_eq!(read, [2, 3, 4, 5]);
+    }
+
+    #[test]
+    fn test_finalize_interval_defers_publication() {
+        for create_queue in test_queue_creators::<u64>() {
+            let (mut producer, mut consumer) = create_queue(4);
+            consumer.set_finalize_interval(NonZeroUsize::new(3).unwrap());
+            assert_eq!(producer.push_slice(&[1, 2, 3, 4]), 4);
+            consumer.sync();
+
+            assert!(consumer.try_read().is_some());
+            assert!(consumer.try_read().is_some());
+            consumer.finalize();
+            producer.sync();
+            assert_eq!(producer.len(), 4);
+
+            assert!(consumer.try_read().is_some());
+            consumer.finalize();
+            producer.sync();
+            assert_eq!(producer.len(), 1);
+
+            assert!(consumer.try_read().is_some());
+            consumer.flush();
+            producer.sync();
+            assert_eq!(producer.len(), 0);
+        }
+    }
+
+    #[test]
+    fn test_finalize_upto_keeps_deferred_finalize() {
+        for create_queue in test_queue_creators::<u64>() {
+            let (mut producer, mut consumer) = create_queue(4);
+            consumer.set_finalize_interval(NonZeroUsize::new(4).unwrap());
+            assert_eq!(producer.push_slice(&[1, 2, 3]), 3);
+            consumer.sync();
+
+            assert_eq!(consumer.try_read(), Some(&1));
+            consumer.finalize();
+            assert_eq!(consumer.try_read(), Some(&2));
+
+            // Only the read not yet finalized is rewound.
+            consumer.finalize_upto(0);
+            assert_eq!(consumer.try_read(), Some(&2));
+            assert_eq!(consumer.try_read(), Some(&3));
+            producer.sync();
+            assert_eq!(producer.len(), 2);
+        }
+    }
+
+    #[test]
+    fn test_drop_publishes_deferred_finalize() {
+        for create_queue in test_queue_creators::<u64>() {
+            let (mut producer, mut consumer) = create_queue(4);
+            consumer.set_finalize_interval(NonZeroUsize::new(4).unwrap());
+            assert_eq!(producer.push_slice(&[1, 2, 3]), 3);
+            consumer.sync();
+
+            assert!(consumer.try_read().is_some());
+            consumer.finalize();
+            // Read but never finalized, so it stays in the queue.
+            assert!(consumer.try_read().is_some());
+            drop(consumer);
+
+            producer.sync();
+            assert_eq!(producer.len(), 2);
+        }
+    }
+
+    #[test]
+    fn test_clear() {
+        for create_queue in test_queue_creators::<u64>() {
+            let (mut producer, mut consumer) = create_queue(4);
+            while producer.push(1).is_ok() {}
+
+            consumer.clear();
+            assert!(consumer.try_read().is_none());
+            consumer.sync();
+            assert!(consumer.try_read().is_none());
+
+            producer.sync();
+            assert!(producer.is_empty());
+            producer.push(2).unwrap();
+            consumer.sync();
+            assert_eq!(consumer.pop(), Some(2));
+        }
+    }
+
+    #[cfg(not(miri))]
+    #[test]
+    fn test_advise() {
+        for create_queue in test_queue_creators::<u64>() {
+            let (mut producer, mut consumer) = create_queue(1024);
+            producer.advise(Advice::WillNeed).unwrap();
+            consumer.advise(Advice::Sequential).unwrap();
+
+            producer.push(1).unwrap();
+            consumer.sync();
+            assert_eq!(consumer.pop(), Some(1));
+        }
+    }
+
+    #[test]
+    fn test_prefetch_next() {
+        for create_queue in test_queue_creators::<u64>() {
+            let (mut producer, mut consumer) = create_queue(4);
+            consumer.prefetch_next(); // empty queue is a no-op
+            producer.push(1).unwrap();
+            producer.push(2).unwrap();
+            consumer.sync();
+            assert_eq!(consumer.pop(), Some(1));
+            consumer.prefetch_next();
+            assert_eq!(consumer.pop(), Some(2));
+        }
+    }
+
+    #[test]
+    fn test_cursors_wrap_around_usize_max() {
+        let region = Region::alloc(NonZeroUsize::new(minimum_region_size::<u64>(4)).unwrap())
+            .expect("failed to allocate region");
+        let header = unsafe { SharedQueueHeader::create_in_region::<u64>(&region, &[]) }.unwrap();
+        // Start two positions below the wrap point: with a power-of-two
+        // capacity dividing 2^64, masking and `wrapping_sub` are unaffected.
+        let start = usize::MAX - 1;
+        unsafe { header.as_ref() }
+            .write
+            .store(start, Ordering::Relaxed);
+        unsafe { header.as_ref() }
+            .read
+            .store(start, Ordering::Relaxed);
+        let mut producer = unsafe { Producer::from_header(Arc::clone(&region), header) }.unwrap();
+        let mut consumer = unsafe { Consumer::from_header(region, header) }.unwrap();
+
+        for round in 0..3 {
+            for i in 0..4 {
+                producer.push(round * 4 + i).unwrap();
+            }
+            assert!(producer.is_full());
+            assert_eq!(producer.push(u64::MAX), Err(u64::MAX));
+
+            consumer.sync();
+            assert_eq!(consumer.len(), 4);
+            for i in 0..4 {
+                assert_eq!(consumer.pop(), Some(round * 4 + i));
+            }
+            assert!(consumer.pop().is_none());
+        }
+    }
+
+    #[test]
+    fn test_push_pop() {
+        for create_queue in test_queue_creators::<u64>() {
+            let (mut producer, mut consumer) = create_queue(2);
+            assert_eq!(consumer.pop(), None);
+
+            assert_eq!(producer.push(1), Ok(()));
+            assert_eq!(producer.push(2), Ok(()));
+            assert_eq!(producer.push(3), Err(3));
+
+            assert_eq!(consumer.pop(), Some(1));
+            // The producer syncs when it appears full.
+            assert_eq!(producer.push(3), Ok(()));
+            assert_eq!(consumer.pop(), Some(2));
+            assert_eq!(consumer.pop(), Some(3));
+            assert_eq!(consumer.pop(), None);
+        }
+    }
+
+    #[test]
+    fn test_reserve_slice_stops_at_wrap() {
+        for create_queue in test_queue_creators::<u64>() {
+            let (mut producer, mut consumer) = create_queue(4);
+
+            let slice = unsafe { producer.reserve_slice(3) };
+            assert_eq!(slice.len(), 3);
+            for (index, slot) in slice.iter_mut().enumerate() {
+                slot.write(index as u64);
+            }
+            producer.commit();
+            assert!(unsafe { producer.reserve_slice(0) }.is_empty());
+
+            consumer.sync();
+            for expected in 0..3 {
+                assert_eq!(consumer.try_read(), Some(&expected));
+            }
+            consumer.finalize();
+            producer.sync();
+
+            // Every slot is free, but only one lies before the wrap point.
+            let slice = unsafe { producer.reserve_slice(4) };
+            assert_eq!(slice.len(), 1);
+            slice[0].write(3);
+            let slice = unsafe { producer.reserve_slice(4) };
+            assert_eq!(slice.len(), 3);
+            for (index, slot) in slice.iter_mut().enumerate() {
+                slot.write(4 + index as u64);
+            }
+            assert!(unsafe { producer.reserve_slice(4) }.is_empty());
+            producer.commit();
+
+            consumer.sync();
+            for expected in 3..7 {
+                assert_eq!(consumer.try_read(), Some(&expected));
+            }
+            consumer.finalize();
+        }
+    }
+
+    #[test]
+    fn test_read_slice_stops_at_wrap() {
+        for create_queue in test_queue_creators::<u64>() {
+            let (mut producer, mut consumer) = create_queue(4);
+            assert!(consumer.read_slice(4).is_empty());
+
+            for value in 0..3 {
+                producer.try_write(value).unwrap();
+            }
+            producer.commit();
+            consumer.sync();
+            assert_eq!(consumer.read_slice(2), &[0, 1]);
+            assert_eq!(consumer.read_slice(4), &[2]);
+            consumer.finalize();
+            producer.sync();
+
+            for value in 3..7 {
+                producer.try_write(value).unwrap();
+            }
+            producer.commit();
+            consumer.sync();
+            // The run stops at the wrap point.
+            assert_eq!(consumer.read_slice(4), &[3]);
+            assert_eq!(consumer.read_slice(4), &[4, 5, 6]);
+            assert!(consumer.read_slice(4).is_empty());
+            consumer.finalize();
+        }
+    }
+
+    #[test]
+    fn test_commit_upto_and_finalize_upto() {
+        for create_queue in test_queue_creators::<u64>() {
+            let (mut producer, mut consumer) = create_queue(4);
+
+            for value in 0..4 {
+                let spot = unsafe { producer.reserve() }.expect("reserve failed");
+                unsafe { spot.write(value) };
+            }
+            producer.commit_upto(2);
+            assert_eq!(producer.len(), 2);
+
+            consumer.sync();
+            assert_eq!(consumer.len(), 2);
+            assert_eq!(consumer.try_read(), Some(&0));
+            assert_eq!(consumer.try_read(), Some(&1));
+            assert_eq!(consumer.try_read(), None);
+
+            // Rewound positions are reserved again.
+            producer.try_write(20).unwrap();
+            producer.commit_upto(usize::MAX);
+            consumer.sync();
+            assert_eq!(consumer.try_read(), Some(&20));
+
+            consumer.finalize_upto(1);
+            producer.sync();
+            assert_eq!(producer.remaining_capacity(), 2);
+            // The unfinalized reads are delivered again.
+            assert_eq!(consumer.try_read(), Some(&1));
+            assert_eq!(consumer.try_read(), Some(&20));
+            consumer.finalize_upto(usize::MAX);
+            producer.sync();
+            assert_eq!(producer.remaining_capacity(), 4);
+        }
+    }
+
+    #[test]
+    fn test_peek_does_not_advance() {
+        for create_queue in test_queue_creators::<u64>() {
+            let (mut producer, mut consumer) = create_queue(4);
+            assert!(consumer.peek().is_none());
+
+            producer.try_write(1).unwrap();
+            producer.try_write(2).unwrap();
+            producer.commit();
+            consumer.sync();
+
+            // SAFETY: peeked pointers point at committed `u64`s.
+            unsafe {
+                assert_eq!(consumer.peek().map(|p| p.read()), Some(1));
+                assert_eq!(consumer.peek_n(1).map(|p| p.read()), Some(2));
+            }
+            assert!(consumer.peek_n(2).is_none());
+            assert_eq!(consumer.len(), 2);
+            assert_eq!(consumer.try_read(), Some(&1));
+            // SAFETY: as above.
+            assert_eq!(consumer.peek().map(|p| unsafe { p.read() }), Some(2));
+        }
+    }
+
+    #[test]
+    fn test_read_guard_finalizes_on_drop() {
+        for create_queue in test_queue_creators::<u64>() {
+            let (mut producer, mut consumer) = create_queue(2);
+            assert!(consumer.try_reserve_read().is_none());
+
+            producer.push(1).unwrap();
+            producer.push(2).unwrap();
+            consumer.sync();
+
+            let guard = consumer.try_reserve_read().expect("reserve read");
+            assert_eq!(*guard.as_ref(), 1);
+            producer.sync();
+            assert!(producer.is_full()); // not finalized while borrowed
+            drop(guard);
+            producer.sync();
+            assert_eq!(producer.remaining_capacity(), 1);
+
+            let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+                let _guard = consumer.try_reserve_read().expect("reserve read");
+                panic!("processing failed");
+            }));
+            assert!(result.is_err());
+            producer.sync();
+            assert_eq!(producer.remaining_capacity(), 2);
+        }
+    }
+
+    #[test]
+    fn test_drain_frees_capacity() {
+        for create_queue in test_queue_creators::<u64>() {
+            let (mut producer, mut consumer) = create_queue(4);
+            for value in 0..4 {
+                producer.push(value).unwrap();
+            }
+
+            let mut drain = consumer.drain();
+            assert_eq!(drain.len(), 4);
+            // SAFETY: drained pointers point at committed `u64`s.
+            assert_eq!(drain.next().map(|p| unsafe { p.read() }), Some(0));
+            // Nothing is finalized until the drain is dropped.
+            producer.sync();
+            assert!(producer.is_full());
+            assert_eq!(
+                drain.map(|p| unsafe { p.read() }).collect::<Vec<_>>(),
+                vec![1, 2, 3]
+            );
+
+            producer.sync();
+            assert_eq!(producer.remaining_capacity(), 4);
+            assert_eq!(consumer.drain().count(), 0);
+        }
+    }
+
+    #[test]
+    fn test_read_ptr_timeout_observes_commit() {
+        for create_queue in test_queue_creators::<u64>() {
+            let (mut producer, mut consumer) = create_queue(64);
+
+            let spot = unsafe { producer.reserve() }.expect("reserve failed");
+            unsafe { spot.write(42) };
+
+            assert!(matches!(
+                consumer.wait_readable_timeout(Duration::ZERO),
+                Err(WaitError::Timeout)
+            ));
+
+            producer.commit();
+
+            let ptr = match consumer.read_ptr_timeout(Duration::ZERO) {
+                Ok(ptr) => ptr,
+                Err(WaitError::Timeout) => panic!("read timed out after commit"),
+            };
+            // SAFETY: `ptr` points at a readable `u64`; the value is Copy.
+            assert_eq!(unsafe { ptr.read() }, 42);
+            consumer.finalize();
+        }
+    }
+
+    #[test]
+    fn test_push_timeout_observes_finalize() {
+        for create_queue in test_queue_creators::<u64>() {
+            let (mut producer, mut consumer) = create_queue(2);
+            producer.push(1).unwrap();
+            producer.push(2).unwrap();
+
+            assert!(matches!(
+                producer.wait_writable_timeout(Duration::ZERO),
+                Err(WaitError::Timeout)
+            ));
+            assert_eq!(producer.push_timeout(3, Duration::ZERO), Err(3));
+
+            consumer.sync();
+            assert_eq!(consumer.try_read().copied(), Some(1));
+            consumer.finalize();
+
+            assert_eq!(producer.push_timeout(3, Duration::ZERO), Ok(()));
+            assert!(matches!(
+                unsafe { producer.reserve_timeout(Duration::ZERO) },
+                Err(WaitError::Timeout)
+            ));
+        }
+    }
+
+    #[cfg(not(miri))]
+    #[test]
+    fn test_push_timeout_wakes_on_finalize() {
+        let (mut producer, mut consumer) = pair::<u64>(1).unwrap();
+        producer.push(1).unwrap();
+
+        let reader = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(10));
+            consumer.sync();
+            assert_eq!(consumer.pop(), Some(1));
+            consumer
+        });
+        producer.set_spin_attempts(0);
+        assert_eq!(producer.push_timeout(2, Duration::MAX), Ok(()));
+
+        let mut consumer = reader.join().unwrap();
+        consumer.sync();
+        assert_eq!(consumer.pop(), Some(2));
+    }
+
+    #[test]
+    fn test_zero_timeout_does_not_spin() {
+        for create_queue in test_queue_creators::<u64>() {
+            let (mut producer, mut consumer) = create_queue(1);
+            consumer.set_spin_attempts(usize::MAX);
+            assert!(matches!(
+                consumer.read_ptr_timeout(Duration::ZERO),
+                Err(WaitError::Timeout)
+            ));
+
+            producer.push(1).unwrap();
+            producer.set_spin_attempts(usize::MAX);
+            assert!(matches!(
+                unsafe { producer.reserve_timeout(Duration::ZERO) },
+                Err(WaitError::Timeout)
+            ));
+            assert!(consumer.read_ptr_timeout(Duration::ZERO).is_ok());
+        }
+    }
+
+    #[cfg(all(target_os = "linux", not(miri)))]
+    #[test]
+    fn test_commit_signals_eventfd() {
+        for create_queue in test_queue_creators::<u64>() {
+            let (mut producer, mut consumer) = create_queue(4);
+            let eventfd = unsafe { libc::eventfd(0, libc::EFD_NONBLOCK) };
+            assert!(eventfd >= 0);
+            producer.set_eventfd(Some(eventfd));
+            consumer.set_readiness_fd(Some(eventfd));
+            assert_eq!(consumer.readiness_fd(), Some(eventfd));
+
+            assert!(!consumer.clear_readiness());
+            producer.push(1).unwrap();
+            producer.push(2).unwrap();
+            assert!(consumer.clear_readiness());
+            assert!(!consumer.clear_readiness());
+
+            producer.set_eventfd(None);
+            producer.push(3).unwrap();
+            assert!(!consumer.clear_readiness());
+
+            unsafe { libc::close(eventfd) };
+        }
+    }
+
+    #[test]
+    fn test_spin_attempts_is_configurable() {
+        for create_queue in test_queue_creators::<u64>() {
+            let (mut producer, mut consumer) = create_queue(64);
+
+            consumer.set_spin_attempts(0);
+            assert!(matches!(
+                consumer.wait_readable_timeout(Duration::from_millis(1)),
+                Err(WaitError::Timeout)
+            ));
+
+            consumer.set_spin_attempts(1 << 20);
+            producer.push(3).unwrap();
+            let ptr = match consumer.read_ptr_timeout(Duration::ZERO) {
+                Ok(ptr) => ptr,
+                Err(WaitError::Timeout) => panic!("read timed out after commit"),
+            };
+            // SAFETY: `ptr` points at a readable `u64`; the value is Copy.
+            assert_eq!(unsafe { ptr.read() }, 3);
+        }
+    }
+
     #[test]
     fn test_wait_readable_max_timeout_does_not_panic() {
         for create_queue in test_queue_creators::<u64>() {
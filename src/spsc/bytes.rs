@@ -0,0 +1,545 @@
+//! Variable-length message framing on top of an SPSC byte queue.
+//!
+//! Each message is stored as a frame: an 8-byte length prefix followed by the
+//! payload, padded so the next frame starts on an 8-byte boundary. A frame
+//! never wraps around the end of the buffer; when one would not fit before
+//! the wrap point, the producer writes a padding marker there instead and
+//! starts the frame at the beginning of the buffer. Readers therefore always
+//! receive a contiguous slice borrowed from the mapping.
+
+use super::{Consumer, Producer};
+use crate::error::Error;
+use std::fs::File;
+
+/// Size of the length prefix, and the alignment of every frame.
+const FRAME_ALIGN: usize = core::mem::size_of::<u64>();
+
+/// Length prefix marking the rest of the buffer, up to the wrap point, as
+/// unused.
+const PADDING: u64 = u64::MAX;
+
+/// Smallest byte capacity that can hold a frame.
+const MIN_CAPACITY: usize = 2 * FRAME_ALIGN;
+
+/// Creates a new in-process byte message queue pair backed by a heap
+/// allocation. `capacity` is in bytes, including framing overhead.
+pub fn byte_pair(capacity: usize) -> Result<(ByteProducer, ByteConsumer), Error> {
+    let (producer, consumer) = super::pair::<u8>(capacity)?;
+    Ok((ByteProducer::new(producer)?, ByteConsumer::new(consumer)?))
+}
+
+/// A frame whose length prefix or padding marker overruns the committed data.
+struct CorruptFrame;
+
+/// Producer side of an SPSC byte message queue.
+pub struct ByteProducer {
+    producer: Producer<u8>,
+}
+
+impl ByteProducer {
+    /// Creates a new producer for the byte message queue in the provided file
+    /// with the given size.
+    ///
+    /// # Safety
+    /// See [`Producer::create`].
+    pub unsafe fn create(file: &File, file_size: usize) -> Result<Self, Error> {
+        // SAFETY: caller upholds the requirements of `Producer::create`.
+        Self::new(unsafe { Producer::create(file, file_size) }?)
+    }
+
+    /// Joins an existing producer for the byte message queue in the provided
+    /// file.
+    ///
+    /// # Safety
+    /// See [`Producer::join`].
+    pub unsafe fn join(file: &File) -> Result<Self, Error> {
+        // SAFETY: caller upholds the requirements of `Producer::join`.
+        Self::new(unsafe { Producer::join(file) }?)
+    }
+
+    fn new(producer: Producer<u8>) -> Result<Self, Error> {
+        if producer.capacity() < MIN_CAPACITY {
+            return Err(Error::InvalidBufferSize);
+        }
+        Ok(Self { producer })
+    }
+
+    /// Return the capacity of the queue in bytes, including framing overhead.
+    pub fn capacity(&self) -> usize {
+        self.producer.capacity()
+    }
+
+    /// Return the length of the largest message the queue can ever hold.
+    pub fn max_message_len(&self) -> usize {
+        self.capacity() - FRAME_ALIGN
+    }
+
+    /// Writes `message` into the queue. Returns `false` if there is not
+    /// enough space, or if the message is longer than
+    /// [`max_message_len`](Self::max_message_len).
+    ///
+    /// The message is not visible to the consumer until [`commit`](Self::commit)
+    /// is called. If the message had to skip the end of the buffer but still
+    /// did not fit, the skip is committed so the consumer can free that space,
+    /// and a later attempt may succeed. Committing the skip also commits every
+    /// message written before it since the last commit, since positions are
+    /// published in order.
+    pub fn try_write_message(&mut self, message: &[u8]) -> bool {
+        self.write_message(message).unwrap_or(false)
+    }
+
+    /// Writes `message` into the queue, like
+    /// [`try_write_message`](Self::try_write_message), but reports a message
+    /// that can never fit as [`Error::MessageTooLarge`]. Returns `Ok(false)`
+    /// if there is not enough space yet.
+    ///
+    /// As with [`try_write_message`](Self::try_write_message), an `Ok(false)`
+    /// after skipping the end of the buffer commits the skip, and with it any
+    /// earlier uncommitted messages.
+    pub fn write_message(&mut self, message: &[u8]) -> Result<bool, Error> {
+        self.write_frame(message, &[])
+    }
+
+    /// Writes `message` followed by its CRC32C into the queue, to be read
+    /// with [`ByteConsumer::try_read_checked_message`]. Returns like
+    /// [`write_message`](Self::write_message); the checksum takes 4 bytes of
+    /// [`max_message_len`](Self::max_message_len).
+    ///
+    /// The checksum detects accidental corruption, such as bad memory or a
+    /// buggy peer writing out of bounds. It does not protect against a
+    /// malicious peer, which can recompute it.
+    #[cfg(feature = "checksum")]
+    pub fn write_checked_message(&mut self, message: &[u8]) -> Result<bool, Error> {
+        let checksum = crate::checksum::crc32c(message).to_le_bytes();
+        self.write_frame(message, &checksum)
+    }
+
+    /// Writes one frame whose payload is `message` followed by `trailer`.
+    fn write_frame(&mut self, message: &[u8], trailer: &[u8]) -> Result<bool, Error> {
+        let payload_len = message.len().saturating_add(trailer.len());
+        if payload_len > self.max_message_len() {
+            return Err(Error::MessageTooLarge {
+                len: payload_len,
+                maximum: self.max_message_len(),
+            });
+        }
+        let frame_len = FRAME_ALIGN + payload_len.next_multiple_of(FRAME_ALIGN);
+
+        let queue = &mut self.producer.queue;
+        let capacity = queue.capacity();
+        let mut offset = queue.mask(queue.cached_write);
+        let until_wrap = capacity - offset;
+        if frame_len > until_wrap {
+            if until_wrap > capacity - queue.len() {
+                return Ok(false);
+            }
+            // SAFETY: `offset` is 8-byte aligned within the buffer and at
+            //         least 8 bytes before the wrap point, and the space is
+            //         free.
+            unsafe { self.write_prefix(offset, PADDING) };
+            let queue = &mut self.producer.queue;
+            queue.cached_write = queue.cached_write.wrapping_add(until_wrap);
+            if frame_len > capacity - queue.len() {
+                self.producer.commit();
+                return Ok(false);
+            }
+            offset = 0;
+        } else if frame_len > capacity - queue.len() {
+            return Ok(false);
+        }
+
+        // SAFETY: the frame fits in free space before the wrap point.
+        unsafe {
+            self.write_prefix(offset, payload_len as u64);
+            let payload = self.producer.queue.buffer.add(offset + FRAME_ALIGN);
+            core::ptr::copy_nonoverlapping(message.as_ptr(), payload.as_ptr(), message.len());
+            core::ptr::copy_nonoverlapping(
+                trailer.as_ptr(),
+                payload.add(message.len()).as_ptr(),
+                trailer.len(),
+            );
+        }
+        let queue = &mut self.producer.queue;
+        queue.cached_write = queue.cached_write.wrapping_add(frame_len);
+        Ok(true)
+    }
+
+    /// # Safety
+    /// - `offset` must leave room for a length prefix before the wrap point.
+    /// - The prefix bytes must not be visible to the consumer.
+    unsafe fn write_prefix(&mut self, offset: usize, prefix: u64) {
+        // SAFETY: caller guarantees the prefix is in bounds and unpublished.
+        unsafe {
+            self.producer
+                .queue
+                .buffer
+                .add(offset)
+                .cast::<u64>()
+                .write_unaligned(prefix)
+        };
+    }
+
+    /// Commits the written messages, making them visible to the consumer.
+    pub fn commit(&self) {
+        self.producer.commit();
+    }
+
+    /// Synchronize the producer's cached read position with the queue's read
+    /// position.
+    pub fn sync(&mut self) {
+        self.producer.sync();
+    }
+}
+
+/// Consumer side of an SPSC byte message queue.
+pub struct ByteConsumer {
+    consumer: Consumer<u8>,
+}
+
+impl ByteConsumer {
+    /// Creates a new consumer for the byte message queue in the provided file
+    /// with the given size.
+    ///
+    /// # Safety
+    /// See [`Consumer::create`].
+    pub unsafe fn create(file: &File, file_size: usize) -> Result<Self, Error> {
+        // SAFETY: caller upholds the requirements of `Consumer::create`.
+        Self::new(unsafe { Consumer::create(file, file_size) }?)
+    }
+
+    /// Joins an existing consumer for the byte message queue in the provided
+    /// file.
+    ///
+    /// # Safety
+    /// See [`Consumer::join`].
+    pub unsafe fn join(file: &File) -> Result<Self, Error> {
+        // SAFETY: caller upholds the requirements of `Consumer::join`.
+        Self::new(unsafe { Consumer::join(file) }?)
+    }
+
+    fn new(consumer: Consumer<u8>) -> Result<Self, Error> {
+        if consumer.capacity() < MIN_CAPACITY {
+            return Err(Error::InvalidBufferSize);
+        }
+        Ok(Self { consumer })
+    }
+
+    /// Return the capacity of the queue in bytes, including framing overhead.
+    pub fn capacity(&self) -> usize {
+        self.consumer.capacity()
+    }
+
+    /// Attempts to read a message from the queue.
+    /// Returns `Ok(None)` if there are no messages available.
+    /// Returns a slice borrowed from the mapping if available.
+    ///
+    /// All read messages should be processed before calling `finalize`.
+    ///
+    /// Returns [`Error::CorruptFrame`] if a frame's length prefix overruns the
+    /// committed data, which means the shared memory was corrupted. The next
+    /// message boundary is then unknown, so everything committed so far is
+    /// discarded.
+    pub fn try_read_message(&mut self) -> Result<Option<&[u8]>, Error> {
+        match self.read_frame() {
+            None => Ok(None),
+            Some(Ok(message)) => Ok(Some(message)),
+            Some(Err(CorruptFrame)) => Err(Error::CorruptFrame),
+        }
+    }
+
+    /// Reads the next frame, skipping padding.
+    ///
+    /// A corrupted frame leaves the next frame boundary unknown, so every
+    /// committed byte is discarded before it is reported.
+    fn read_frame(&mut self) -> Option<Result<&[u8], CorruptFrame>> {
+        let queue = &mut self.consumer.queue;
+        loop {
+            let available = queue.len();
+            if available == 0 {
+                return None;
+            }
+            let offset = queue.mask(queue.cached_read);
+            let until_wrap = queue.capacity() - offset;
+            if available < FRAME_ALIGN {
+                break;
+            }
+
+            // SAFETY: `offset` is in bounds, committed, and at least 8 bytes
+            //         before the wrap point since every frame is padded to 8.
+            let prefix = unsafe { queue.buffer.add(offset).cast::<u64>().read_unaligned() };
+            if prefix == PADDING {
+                if available < until_wrap {
+                    break;
+                }
+                queue.cached_read = queue.cached_read.wrapping_add(until_wrap);
+                continue;
+            }
+
+            let len = usize::try_from(prefix).unwrap_or(usize::MAX);
+            let Some(frame_len) = len
+                .checked_next_multiple_of(FRAME_ALIGN)
+                .and_then(|len| len.checked_add(FRAME_ALIGN))
+                .filter(|&frame_len| frame_len <= available.min(until_wrap))
+            else {
+                break;
+            };
+            queue.cached_read = queue.cached_read.wrapping_add(frame_len);
+
+            // SAFETY: the payload is committed and lies within the buffer.
+            return Some(Ok(unsafe {
+                core::slice::from_raw_parts(queue.buffer.add(offset + FRAME_ALIGN).as_ptr(), len)
+            }));
+        }
+
+        queue.cached_read = queue.cached_write;
+        Some(Err(CorruptFrame))
+    }
+
+    /// Attempts to read a message written by
+    /// [`ByteProducer::write_checked_message`], verifying its checksum.
+    /// Returns `None` if there are no messages available, and
+    /// [`Error::ChecksumMismatch`] if the message was corrupted; the message
+    /// is consumed either way.
+    ///
+    /// A corrupted length prefix is also reported as
+    /// [`Error::ChecksumMismatch`], and discards everything committed so far
+    /// as [`try_read_message`](Self::try_read_message) does.
+    #[cfg(feature = "checksum")]
+    pub fn try_read_checked_message(&mut self) -> Option<Result<&[u8], Error>> {
+        const CHECKSUM_LEN: usize = core::mem::size_of::<u32>();
+
+        let Ok(frame) = self.read_frame()? else {
+            return Some(Err(Error::ChecksumMismatch));
+        };
+        let Some(split) = frame.len().checked_sub(CHECKSUM_LEN) else {
+            return Some(Err(Error::ChecksumMismatch));
+        };
+        let (message, checksum) = frame.split_at(split);
+        let checksum = u32::from_le_bytes(checksum.try_into().expect("4-byte checksum"));
+        if crate::checksum::crc32c(message) != checksum {
+            return Some(Err(Error::ChecksumMismatch));
+        }
+        Some(Ok(message))
+    }
+
+    /// Publishes the read position, making it visible to the producer.
+    /// All previously read messages MUST be processed before this is called.
+    pub fn finalize(&mut self) {
+        self.consumer.finalize();
+    }
+
+    /// Synchronizes the consumer's cached write position with the queue's write position.
+    pub fn sync(&mut self) {
+        self.consumer.sync();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(not(miri))]
+    use crate::shmem::create_temp_shmem_file;
+
+    #[test]
+    fn test_write_read_messages() {
+        let (mut producer, mut consumer) = byte_pair(64).unwrap();
+        assert_eq!(producer.max_message_len(), 56);
+
+        assert!(producer.try_write_message(b"hello"));
+        assert!(producer.try_write_message(b""));
+        assert!(producer.try_write_message(b"world!!!"));
+        assert_eq!(consumer.try_read_message().unwrap(), None); // not committed yet
+        producer.commit();
+        consumer.sync();
+
+        assert_eq!(consumer.try_read_message().unwrap(), Some(&b"hello"[..]));
+        assert_eq!(consumer.try_read_message().unwrap(), Some(&b""[..]));
+        assert_eq!(consumer.try_read_message().unwrap(), Some(&b"world!!!"[..]));
+        assert_eq!(consumer.try_read_message().unwrap(), None);
+    }
+
+    #[test]
+    fn test_rejects_oversized_message() {
+        let (mut producer, _consumer) = byte_pair(64).unwrap();
+        assert!(!producer.try_write_message(&[0; 57]));
+        assert!(matches!(
+            producer.write_message(&[0; 57]),
+            Err(Error::MessageTooLarge {
+                len: 57,
+                maximum: 56
+            })
+        ));
+        assert!(producer.try_write_message(&[0; 56]));
+    }
+
+    #[test]
+    fn test_rejects_too_small_capacity() {
+        assert!(matches!(byte_pair(8), Err(Error::InvalidBufferSize)));
+    }
+
+    #[test]
+    fn test_message_skips_wrap_point() {
+        let (mut producer, mut consumer) = byte_pair(64).unwrap();
+
+        // Fill 40 of 64 bytes, then free them.
+        assert!(producer.try_write_message(&[1; 32]));
+        producer.commit();
+        consumer.sync();
+        assert_eq!(consumer.try_read_message().unwrap(), Some(&[1; 32][..]));
+        consumer.finalize();
+        producer.sync();
+
+        // 24 bytes remain before the wrap point, too few for this frame.
+        assert!(producer.try_write_message(&[2; 20]));
+        producer.commit();
+        consumer.sync();
+        assert_eq!(consumer.try_read_message().unwrap(), Some(&[2; 20][..]));
+        assert_eq!(consumer.try_read_message().unwrap(), None);
+    }
+
+    #[test]
+    fn test_full_size_message_after_skip() {
+        let (mut producer, mut consumer) = byte_pair(64).unwrap();
+        assert!(producer.try_write_message(&[1; 8]));
+        producer.commit();
+        consumer.sync();
+        consumer.try_read_message().unwrap().unwrap();
+        consumer.finalize();
+        producer.sync();
+
+        // The skip is committed even though the frame does not fit yet.
+        assert!(!producer.try_write_message(&[2; 56]));
+        consumer.sync();
+        assert_eq!(consumer.try_read_message().unwrap(), None);
+        consumer.finalize();
+        producer.sync();
+
+        assert!(producer.try_write_message(&[2; 56]));
+        producer.commit();
+        consumer.sync();
+        assert_eq!(consumer.try_read_message().unwrap(), Some(&[2; 56][..]));
+    }
+
+    #[test]
+    fn test_skip_commits_earlier_messages() {
+        let (mut producer, mut consumer) = byte_pair(64).unwrap();
+        assert!(producer.try_write_message(&[1; 8]));
+        producer.commit();
+        consumer.sync();
+        consumer.try_read_message().unwrap().unwrap();
+        consumer.finalize();
+        producer.sync();
+
+        // Written but not committed, then published by the skip.
+        assert!(producer.try_write_message(&[3; 8]));
+        assert!(!producer.try_write_message(&[2; 40]));
+        consumer.sync();
+        assert_eq!(consumer.try_read_message().unwrap(), Some(&[3; 8][..]));
+        assert_eq!(consumer.try_read_message().unwrap(), None);
+    }
+
+    #[test]
+    fn test_corrupted_prefix_is_reported() {
+        let (mut producer, mut consumer) = byte_pair(64).unwrap();
+        assert!(producer.try_write_message(b"intact"));
+        assert!(producer.try_write_message(b"overrun"));
+        assert!(producer.try_write_message(b"lost"));
+        producer.commit();
+
+        // Point the second frame's prefix, at byte 16, past the committed
+        // data.
+        unsafe { producer.write_prefix(16, 1024) };
+
+        consumer.sync();
+        assert_eq!(consumer.try_read_message().unwrap(), Some(&b"intact"[..]));
+        assert!(matches!(
+            consumer.try_read_message(),
+            Err(Error::CorruptFrame)
+        ));
+        assert_eq!(consumer.try_read_message().unwrap(), None);
+    }
+
+    #[cfg(feature = "checksum")]
+    #[test]
+    fn test_checked_message_detects_corruption() {
+        let (mut producer, mut consumer) = byte_pair(64).unwrap();
+        assert!(producer.write_checked_message(b"intact").unwrap());
+        assert!(producer.write_checked_message(b"flipped").unwrap());
+        producer.commit();
+
+        // Flip a payload bit of the second frame, which starts at byte 24.
+        unsafe {
+            let byte = producer.producer.queue.buffer.add(24 + FRAME_ALIGN);
+            byte.write(byte.read() ^ 1);
+        }
+
+        consumer.sync();
+        assert_eq!(
+            consumer.try_read_checked_message().unwrap().unwrap(),
+            b"intact"
+        );
+        assert!(matches!(
+            consumer.try_read_checked_message(),
+            Some(Err(Error::ChecksumMismatch))
+        ));
+        assert!(consumer.try_read_checked_message().is_none());
+    }
+
+    #[cfg(feature = "checksum")]
+    #[test]
+    fn test_checked_message_reports_corrupted_prefix() {
+        let (mut producer, mut consumer) = byte_pair(64).unwrap();
+        assert!(producer.write_checked_message(b"intact").unwrap());
+        assert!(producer.write_checked_message(b"overrun").unwrap());
+        assert!(producer.write_checked_message(b"lost").unwrap());
+        producer.commit();
+
+        // Point the second frame's prefix, at byte 24, past the committed
+        // data.
+        unsafe { producer.write_prefix(24, 1024) };
+
+        consumer.sync();
+        assert_eq!(
+            consumer.try_read_checked_message().unwrap().unwrap(),
+            b"intact"
+        );
+        assert!(matches!(
+            consumer.try_read_checked_message(),
+            Some(Err(Error::ChecksumMismatch))
+        ));
+        assert!(consumer.try_read_checked_message().is_none());
+    }
+
+    #[test]
+    fn test_full_queue_rejects_message() {
+        let (mut producer, mut consumer) = byte_pair(32).unwrap();
+        assert!(producer.try_write_message(&[1; 8]));
+        assert!(producer.try_write_message(&[2; 8]));
+        assert!(!producer.try_write_message(&[3; 1]));
+        producer.commit();
+
+        consumer.sync();
+        consumer.try_read_message().unwrap().unwrap();
+        consumer.finalize();
+        assert!(!producer.try_write_message(&[3; 1])); // producer has not synced yet
+        producer.sync();
+        assert!(producer.try_write_message(&[3; 1]));
+    }
+
+    #[cfg(not(miri))]
+    #[test]
+    fn test_file_backed_byte_queue() {
+        let file = create_temp_shmem_file().unwrap();
+        let file_size = super::super::minimum_file_size::<u8>(256);
+        let mut producer = unsafe { ByteProducer::create(&file, file_size) }.unwrap();
+        let mut consumer = unsafe { ByteConsumer::join(&file) }.unwrap();
+
+        assert!(producer.try_write_message(b"across the mapping"));
+        producer.commit();
+        consumer.sync();
+        assert_eq!(
+            consumer.try_read_message().unwrap(),
+            Some(&b"across the mapping"[..])
+        );
+    }
+}